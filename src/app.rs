@@ -1,158 +1,1131 @@
 //! Application state and logic.
 
+use crate::backup;
+use crate::config::{Config, EditFormat};
+use crate::editing;
 use crate::git;
-use crate::persistence;
-use crate::tasks::{Task, TaskManager};
+use crate::input::Keymap;
+use crate::merge;
+use crate::persistence::{self, ParseError};
+use crate::sync::{self, SyncAction, SyncBackend, SyncWorker};
+use crate::tasks::{Blocked, Task, TaskList, TaskManager};
+use crate::watcher::TasksWatcher;
+use chrono::{DateTime, Local};
+use serde::Serialize;
 use std::fs;
-use std::io::{self, Write};
+use std::io;
+use std::path::Path;
 use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tempfile::NamedTempFile;
 
+/// A goal as listed in the goal picker.
+#[derive(Serialize)]
+pub struct GoalEntry {
+    /// Name of the goal directory.
+    pub name: String,
+    /// The goal title, or an error note if its tasks could not be loaded.
+    pub title: String,
+    /// Number of done tasks.
+    pub done: usize,
+    /// Total number of tasks.
+    pub total: usize,
+}
+
+/// Lists all goals in `data_dir` with their progress.
+pub fn goal_entries(data_dir: &Path) -> io::Result<Vec<GoalEntry>> {
+    Ok(persistence::list_goals(data_dir)?
+        .into_iter()
+        .map(
+            |name| match persistence::read_tasks(&persistence::get_goal_dir(data_dir, &name)) {
+                Ok(list) => GoalEntry {
+                    title: list.the_goal,
+                    done: list.tasks.iter().filter(|t| t.done).count(),
+                    total: list.tasks.len(),
+                    name,
+                },
+                Err(e) => GoalEntry {
+                    title: format!("(failed to load: {})", e),
+                    done: 0,
+                    total: 0,
+                    name,
+                },
+            },
+        )
+        .collect())
+}
+
+/// State of the goal picker.
+pub struct GoalPicker {
+    /// Available goals.
+    pub goals: Vec<GoalEntry>,
+    /// Index of the highlighted goal.
+    pub selected: usize,
+}
+
+/// The screen shown in the main area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    /// The current task.
+    Tasks,
+    /// Progress statistics.
+    Stats,
+    /// Calendar heatmap of completed tasks.
+    Heatmap,
+    /// Keybindings.
+    Help,
+    /// Earlier versions of the task list.
+    History,
+}
+
+/// Maximum number of versions listed in the history.
+const HISTORY_LIMIT: usize = 200;
+
+/// Minimum time between two refreshes of the repository status after edits.
+const REPO_STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A version of the task list in the history.
+pub struct HistoryEntry {
+    /// The commit that created this version.
+    pub commit: git::Commit,
+    /// Which tasks changed in this version, e.g. "Done #3: Write parser".
+    pub summary: String,
+    /// The task list, or `None` if it could not be parsed.
+    pub tasks: Option<TaskList>,
+}
+
+/// State of the history view.
+pub struct History {
+    /// Versions of the task list, newest first.
+    pub entries: Vec<HistoryEntry>,
+    /// Index of the highlighted version.
+    pub selected: usize,
+}
+
+/// A single-line text prompt shown over the main area.
+pub struct Prompt {
+    /// What is being asked for.
+    pub label: String,
+    /// The text entered so far.
+    pub input: String,
+    /// What to do with the text once it is submitted.
+    pub action: PromptAction,
+}
+
+/// What to do with the text of a prompt once it is submitted.
+#[derive(Clone, Copy)]
+pub enum PromptAction {
+    /// Add the text as the remote URL, then run the sync.
+    AddRemote(SyncAction),
+    /// Create a goal with the text as its title and switch to it.
+    NewGoal,
+}
+
+/// A popup with details, e.g. the output of a failed git command.
+pub struct Popup {
+    /// Title of the popup.
+    pub title: String,
+    /// Text of the popup.
+    pub body: String,
+}
+
 /// Main application struct.
 pub struct App {
+    /// User configuration.
+    pub config: Config,
+    /// Keys bound to input events.
+    pub keymap: Keymap,
+    /// Where goals are synced to.
+    backend: Arc<dyn SyncBackend>,
     /// Task manager.
     pub task_manager: TaskManager,
+    /// Name of the active goal.
+    pub goal: String,
+    /// The screen shown in the main area.
+    pub view: View,
+    /// The goal picker, if it is open.
+    pub goal_picker: Option<GoalPicker>,
+    /// The history of the task list, while it is shown.
+    pub history: Option<History>,
+    /// The live tasks, stashed while an earlier version is previewed in `task_manager`.
+    live_tasks: Option<TaskManager>,
+    /// Index of the highlighted checklist item of the current task.
+    pub checklist_index: usize,
+    /// A message to show to the user until the next key press.
+    pub message: Option<String>,
+    /// The open text prompt, if any.
+    pub prompt: Option<Prompt>,
+    /// The open popup, if any.
+    pub popup: Option<Popup>,
+    /// The sync running in the background, if any.
+    pub sync: Option<SyncWorker>,
+    /// When the active goal was last synced successfully.
+    pub last_synced: Option<DateTime<Local>>,
+    /// How the active goal compares to its remote, if it has one.
+    pub repo_status: Option<git::RepoStatus>,
+    /// When `repo_status` was last computed.
+    repo_status_at: Instant,
+    /// The tasks as they were when the running sync started.
+    sync_base: Option<TaskList>,
+    /// Watches the tasks file of the active goal for changes by other programs.
+    watcher: Option<TasksWatcher>,
     /// If true, the application should quit.
     pub should_quit: bool,
 }
 
 impl App {
-    /// Creates a new `App`.
-    pub fn new() -> io::Result<App> {
-        let tasks_list = persistence::load_tasks()?;
-        let mut task_manager = TaskManager::new(tasks_list)?;
+    /// Creates a new `App` for `goal`, or for the last active goal if `None`.
+    ///
+    /// If configured, a pull of the goal is started in the background.
+    pub fn new(config: Config, goal: Option<String>) -> io::Result<App> {
+        let goal = goal.unwrap_or_else(|| persistence::load_active_goal(&config.data_dir));
+        let dir = persistence::existing_goal_dir(&config.data_dir, &goal)?;
+        let tasks_list = persistence::read_tasks(&dir)?;
+        let mut task_manager = TaskManager::new(tasks_list, dir)?;
         task_manager.first_undone();
-        Ok(App {
+        let keymap = Keymap::new(&config.keybindings)?;
+        let backend = sync::backend(&config);
+        let mut app = App {
+            keymap,
+            backend,
+            config,
             task_manager,
+            goal,
+            view: View::Tasks,
+            goal_picker: None,
+            history: None,
+            live_tasks: None,
+            checklist_index: 0,
+            message: None,
+            prompt: None,
+            popup: None,
+            sync: None,
+            last_synced: None,
+            repo_status: None,
+            repo_status_at: Instant::now(),
+            sync_base: None,
+            watcher: None,
             should_quit: false,
-        })
+        };
+        app.watch_tasks();
+        app.back_up_tasks();
+        if let Err(e) = app.pull_on_start() {
+            app.report_sync_error(SyncAction::Pull, e);
+        }
+        app.refresh_repo_status();
+        Ok(app)
     }
 
-    /// Reloads tasks from disk.
+    /// Reloads tasks of the active goal from disk.
     pub fn reload_tasks(&mut self) -> io::Result<()> {
-        let tasks_list = persistence::load_tasks()?;
-        self.task_manager = TaskManager::new(tasks_list)?;
+        let dir = persistence::existing_goal_dir(&self.config.data_dir, &self.goal)?;
+        let tasks_list = persistence::read_tasks(&dir)?;
+        self.task_manager = TaskManager::new(tasks_list, dir)?;
         self.task_manager.first_undone();
+        self.checklist_index = 0;
         Ok(())
     }
 
-    /// Signals the application to quit.
+    /// Watches the tasks file of the active goal, so that changes made by other
+    /// programs are reloaded. Watching is skipped if the platform doesn't support it.
+    fn watch_tasks(&mut self) {
+        self.watcher = TasksWatcher::new(&self.task_manager.dir).ok();
+    }
+
+    /// Takes a snapshot of the tasks file of the active goal, as configured in `[backup]`.
+    fn back_up_tasks(&mut self) {
+        if let Err(e) = backup::snapshot(&self.config.data_dir, &self.goal, &self.config.backup) {
+            self.message = Some(format!("Backing up the tasks failed: {}", e));
+        }
+    }
+
+    /// Switches to the given goal and remembers it for the next run.
+    pub fn switch_goal(&mut self, goal: &str) -> io::Result<()> {
+        let previous = std::mem::replace(&mut self.goal, goal.to_string());
+        if let Err(e) = self.reload_tasks() {
+            self.goal = previous;
+            return Err(e);
+        }
+        self.watch_tasks();
+        self.back_up_tasks();
+        self.last_synced = None;
+        self.history = None;
+        if self.view == View::History {
+            self.view = View::Tasks;
+        }
+        self.refresh_repo_status();
+        persistence::persist_active_goal(&self.config.data_dir, goal)
+    }
+
+    /// Switches between the task view and the statistics screen.
+    pub fn toggle_stats(&mut self) {
+        self.toggle_view(View::Stats);
+    }
+
+    /// Switches between the task view and the completion heatmap.
+    pub fn toggle_heatmap(&mut self) {
+        self.toggle_view(View::Heatmap);
+    }
+
+    /// Switches between the task view and the help screen.
+    pub fn toggle_help(&mut self) {
+        self.toggle_view(View::Help);
+    }
+
+    /// Switches between the task view and the history of the task list.
+    pub fn toggle_history(&mut self) {
+        if self.view == View::History {
+            self.view = View::Tasks;
+            self.history = None;
+            return;
+        }
+        match self.load_history() {
+            Ok(history) => {
+                self.history = Some(history);
+                self.view = View::History;
+            }
+            Err(e) => self.show_error("Loading the history failed", e),
+        }
+    }
+
+    fn load_history(&self) -> git::Result<History> {
+        let dir = &self.task_manager.dir;
+        if !dir.join(".git").exists() {
+            return Ok(History {
+                entries: Vec::new(),
+                selected: 0,
+            });
+        }
+        let entries = git::log(dir, HISTORY_LIMIT)?
+            .into_iter()
+            .map(|version| {
+                let summary = match &version.tasks {
+                    Some(tasks) => git::describe_changes(version.previous.as_ref(), tasks),
+                    None => "(tasks file could not be read)".to_string(),
+                };
+                HistoryEntry {
+                    commit: version.commit,
+                    summary,
+                    tasks: version.tasks,
+                }
+            })
+            .collect();
+        Ok(History {
+            entries,
+            selected: 0,
+        })
+    }
+
+    /// Highlights the next (older) version in the history.
+    pub fn next_version(&mut self) {
+        if let Some(history) = &mut self.history {
+            if history.selected + 1 < history.entries.len() {
+                history.selected += 1;
+            }
+        }
+    }
+
+    /// Highlights the previous (newer) version in the history.
+    pub fn previous_version(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.selected = history.selected.saturating_sub(1);
+        }
+    }
+
+    /// Returns the highlighted version in the history.
+    pub fn selected_version(&self) -> Option<&HistoryEntry> {
+        let history = self.history.as_ref()?;
+        history.entries.get(history.selected)
+    }
+
+    /// Returns `true` if an earlier version is shown instead of the live tasks.
+    pub fn is_previewing(&self) -> bool {
+        self.live_tasks.is_some()
+    }
+
+    /// Shows the highlighted version in the task view without changing anything on disk.
+    pub fn preview_version(&mut self) {
+        if self.sync.is_some() {
+            self.message = Some("Wait for the sync to finish to preview a version".to_string());
+            return;
+        }
+        let Some(tasks) = self
+            .selected_version()
+            .and_then(|entry| entry.tasks.clone())
+        else {
+            return;
+        };
+        if tasks.tasks.is_empty() {
+            self.message = Some("This version has no tasks".to_string());
+            return;
+        }
+        let mut preview = match TaskManager::new(tasks, self.task_manager.dir.clone()) {
+            Ok(preview) => preview,
+            Err(e) => {
+                self.message = Some(format!("Failed to preview the version: {}", e));
+                return;
+            }
+        };
+        preview.defer_saves = true;
+        preview.first_undone();
+        self.live_tasks = Some(std::mem::replace(&mut self.task_manager, preview));
+        self.checklist_index = 0;
+        self.view = View::Tasks;
+    }
+
+    /// Goes back from a preview to the history.
+    pub fn end_preview(&mut self) {
+        if let Some(live_tasks) = self.live_tasks.take() {
+            self.task_manager = live_tasks;
+            self.checklist_index = 0;
+            self.view = View::History;
+        }
+    }
+
+    /// Writes the highlighted version back as the current tasks and commits it.
+    pub fn restore_version(&mut self) {
+        if self.sync.is_some() {
+            self.message = Some("Wait for the sync to finish to restore a version".to_string());
+            return;
+        }
+        let Some(entry) = self.selected_version() else {
+            return;
+        };
+        let Some(tasks) = entry.tasks.clone().filter(|tasks| !tasks.tasks.is_empty()) else {
+            self.message = Some("This version can't be restored".to_string());
+            return;
+        };
+        let commit = entry.commit.clone();
+        if let Some(live_tasks) = self.live_tasks.take() {
+            self.task_manager = live_tasks;
+        }
+        match self.write_version(&tasks, &commit) {
+            Ok(()) => {
+                self.history = None;
+                self.view = View::Tasks;
+                self.message = Some(format!(
+                    "Restored the version of {}",
+                    commit.date.format("%Y-%m-%d %H:%M")
+                ));
+            }
+            Err(e) => self.show_error("Restoring the version failed", e),
+        }
+        self.refresh_repo_status();
+    }
+
+    fn write_version(&mut self, tasks: &TaskList, commit: &git::Commit) -> git::Result<()> {
+        let dir = self.task_manager.dir.clone();
+        // Commit uncommitted edits first, so that the restore doesn't lose them.
+        git::commit(&dir, None)?;
+        self.task_manager.replace(tasks.clone());
+        self.checklist_index = 0;
+        self.task_manager.save()?;
+        git::commit(&dir, Some(&format!("Restore tasks from {}", commit.id)))
+    }
+
+    /// Shows `view`, or the task view if `view` is already shown.
+    fn toggle_view(&mut self, view: View) {
+        self.view = if self.view == view { View::Tasks } else { view };
+    }
+
+    /// Opens the goal picker with the active goal highlighted.
+    pub fn open_goal_picker(&mut self) {
+        if self.sync.is_some() {
+            self.message = Some("Wait for the sync to finish to switch goals".to_string());
+            return;
+        }
+        match goal_entries(&self.config.data_dir) {
+            Ok(goals) => {
+                let selected = goals.iter().position(|g| g.name == self.goal).unwrap_or(0);
+                self.goal_picker = Some(GoalPicker { goals, selected });
+            }
+            Err(e) => self.message = Some(format!("Failed to list the goals: {}", e)),
+        }
+    }
+
+    /// Closes the goal picker.
+    pub fn close_goal_picker(&mut self) {
+        self.goal_picker = None;
+    }
+
+    /// Highlights the next goal in the picker.
+    pub fn next_goal(&mut self) {
+        if let Some(picker) = &mut self.goal_picker {
+            if picker.selected + 1 < picker.goals.len() {
+                picker.selected += 1;
+            }
+        }
+    }
+
+    /// Highlights the previous goal in the picker.
+    pub fn previous_goal(&mut self) {
+        if let Some(picker) = &mut self.goal_picker {
+            picker.selected = picker.selected.saturating_sub(1);
+        }
+    }
+
+    /// Switches to the highlighted goal and closes the picker.
+    pub fn select_goal(&mut self) {
+        let goal = self
+            .goal_picker
+            .take()
+            .and_then(|picker| picker.goals.into_iter().nth(picker.selected))
+            .map(|entry| entry.name);
+        if let Some(goal) = goal {
+            if let Err(e) = self.switch_goal(&goal) {
+                self.message = Some(format!("Failed to switch to goal '{}': {}", goal, e));
+            }
+        }
+    }
+
+    /// Asks for the title of a new goal, which is created once the prompt is submitted.
+    pub fn new_goal(&mut self) {
+        self.prompt = Some(Prompt {
+            label: "Title of the new goal".to_string(),
+            input: String::new(),
+            action: PromptAction::NewGoal,
+        });
+    }
+
+    /// Creates a goal with `title` and switches to it.
+    fn create_goal(&mut self, title: &str) {
+        if title.is_empty() {
+            self.message = Some("No goal title given".to_string());
+            return;
+        }
+        let created = persistence::create_goal(&self.config.data_dir, title)
+            .and_then(|goal| self.switch_goal(&goal));
+        match created {
+            Ok(()) => self.goal_picker = None,
+            Err(e) => self.message = Some(format!("Failed to create the goal: {}", e)),
+        }
+    }
+
+    /// Signals the application to quit, unless that would lose changes.
+    ///
+    /// A running sync is waited for, then unsaved changes are saved. If the tasks
+    /// changed on disk, the conflict prompt is shown again instead of quitting; if
+    /// saving fails otherwise, quitting again discards the changes.
     pub fn quit(&mut self) {
+        self.wait_for_sync();
+        if self.task_manager.unsaved {
+            if let Err(e) = self.task_manager.save() {
+                if !self.task_manager.conflict {
+                    self.task_manager.unsaved = false;
+                    self.popup = Some(Popup {
+                        title: "Saving the tasks failed".to_string(),
+                        body: format!("{}\n\nQuit again to discard the unsaved changes.", e),
+                    });
+                }
+                return;
+            }
+        }
         self.should_quit = true;
     }
 
     /// Marks the current task as done.
     pub fn mark_done(&mut self) {
-        self.task_manager.mark_done();
-        self.task_manager.next_undone();
+        match self.task_manager.mark_done() {
+            Err(e) if Blocked::find(&e).is_some() => self.message = Some(e.to_string()),
+            result => {
+                self.task_manager.next_undone();
+                self.checklist_index = 0;
+                self.check_saved(result);
+            }
+        }
     }
 
     /// Marks the current task as not done.
     pub fn mark_undone(&mut self) {
-        self.task_manager.mark_undone();
+        let result = self.task_manager.mark_undone();
+        self.check_saved(result);
     }
 
     /// Moves to the next task.
     pub fn next_task(&mut self) {
         self.task_manager.next();
+        self.checklist_index = 0;
     }
 
     /// Moves to the previous task.
     pub fn previous_task(&mut self) {
         self.task_manager.previous();
+        self.checklist_index = 0;
     }
 
     /// Moves to the next undone task.
     pub fn next_undone_task(&mut self) {
         self.task_manager.next_undone();
+        self.checklist_index = 0;
     }
 
     /// Moves to the first undone task.
     pub fn first_undone_task(&mut self) {
         self.task_manager.first_undone();
+        self.checklist_index = 0;
     }
 
     /// Moves to the last task.
     pub fn last_task(&mut self) {
         self.task_manager.last();
+        self.checklist_index = 0;
+    }
+
+    /// Highlights the next checklist item of the current task.
+    pub fn next_checklist_item(&mut self) {
+        let len = self.task_manager.current_task().checklist.len();
+        if self.checklist_index + 1 < len {
+            self.checklist_index += 1;
+        }
+    }
+
+    /// Highlights the previous checklist item of the current task.
+    pub fn previous_checklist_item(&mut self) {
+        self.checklist_index = self.checklist_index.saturating_sub(1);
+    }
+
+    /// Toggles the highlighted checklist item of the current task.
+    pub fn toggle_checklist_item(&mut self) {
+        let result = self
+            .task_manager
+            .toggle_checklist_item(self.checklist_index);
+        self.check_saved(result);
     }
 
     /// Opens the current task in an editor for modification.
-    pub fn edit_task(&mut self) -> io::Result<()> {
+    pub fn edit_task(&mut self) {
         let task = self.task_manager.current_task().clone();
-        let edited_task = self.get_task_from_editor(Some(task))?;
-        if let Some(edited_task) = edited_task {
-            self.task_manager.update_task(edited_task);
+        match self.get_task_from_editor(Some(task)) {
+            Ok(Some(edited_task)) => {
+                let result = self.task_manager.update_task(edited_task);
+                self.checklist_index = 0;
+                self.check_saved(result);
+            }
+            Ok(None) => self.message = Some("Edit cancelled".to_string()),
+            Err(e) => self.message = Some(format!("Failed to edit the task: {}", e)),
         }
-        Ok(())
     }
 
     /// Creates a new task.
-    pub fn new_task(&mut self) -> io::Result<()> {
-        let new_task = self.get_task_from_editor(None)?;
-        if let Some(new_task) = new_task {
-            self.task_manager.add_task(new_task);
+    pub fn new_task(&mut self) {
+        match self.get_task_from_editor(None) {
+            Ok(Some(new_task)) => {
+                let result = self.task_manager.add_task(new_task);
+                self.check_saved(result);
+            }
+            Ok(None) => self.message = Some("New task cancelled".to_string()),
+            Err(e) => self.message = Some(format!("Failed to add the task: {}", e)),
         }
-        Ok(())
     }
 
+    /// Lets the user edit `task`, or a new task if `None`, in an editor, in the
+    /// configured format.
+    ///
+    /// Until the file parses into a task that can be saved, the editor is reopened
+    /// with the error in a comment on top. Returns `None` if the user left the file
+    /// unchanged or emptied it. The task keeps its id, even if the user changed it.
     fn get_task_from_editor(&self, task: Option<Task>) -> io::Result<Option<Task>> {
-        let mut file = NamedTempFile::new()?;
+        let format = self.config.edit_format;
+        let file = NamedTempFile::with_suffix(editing::extension(format))?;
         let task = task.unwrap_or_else(|| {
-            let last_id = self.task_manager.tasks.last().map(|t| t.id).unwrap_or(0);
-            Task {
-                id: last_id + 1,
-                title: "New Task".to_string(),
-                description: "Task description".to_string(),
-                done: false,
-            }
+            Task::new(self.task_manager.next_id(), "New Task", "Task description")
         });
-        let toml = toml::to_string_pretty(&task).map_err(io::Error::other)?;
-        file.write_all(toml.as_bytes())?;
+        let mut content = editing::to_text(&task, format)?;
+        let editor = self.config.editor_command();
+        loop {
+            fs::write(file.path(), &content)?;
+            Command::new(&editor[0])
+                .args(&editor[1..])
+                .arg(file.path())
+                .status()?;
+
+            let edited = fs::read_to_string(file.path())?;
+            let check = |task: &Task| self.task_manager.check_task(task);
+            match check_edit(&content, &edited, task.id, format, check) {
+                EditResult::Cancelled => return Ok(None),
+                EditResult::Edited(task) => return Ok(Some(task)),
+                EditResult::Retry(retry) => content = retry,
+            }
+        }
+    }
+
+    /// Pushes tasks of the active goal, asking for a remote URL first if none is configured.
+    pub fn push_tasks(&mut self) {
+        self.request_sync(SyncAction::Push);
+    }
+
+    /// Pulls tasks of the active goal, asking for a remote URL first if none is configured.
+    pub fn pull_tasks(&mut self) {
+        self.request_sync(SyncAction::Pull);
+    }
+
+    fn request_sync(&mut self, action: SyncAction) {
+        if self.sync.is_some() {
+            self.message = Some("A sync is already running".to_string());
+            return;
+        }
+        match self.has_remote() {
+            Ok(true) => self.start_sync(action),
+            Ok(false) => match self.backend.remote_prompt() {
+                Some(label) => {
+                    self.prompt = Some(Prompt {
+                        label: label.to_string(),
+                        input: String::new(),
+                        action: PromptAction::AddRemote(action),
+                    })
+                }
+                None => {
+                    self.message = Some(
+                        "No sync location configured; set it in the configuration file".to_string(),
+                    )
+                }
+            },
+            Err(e) => self.report_sync_error(action, e),
+        }
+    }
+
+    /// Runs the action of the open prompt with the entered text and closes the prompt.
+    pub fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        let input = prompt.input.trim();
+        match prompt.action {
+            PromptAction::AddRemote(action) => self.add_remote(action, input),
+            PromptAction::NewGoal => self.create_goal(input),
+        }
+    }
+
+    /// Adds `url` as the remote of the active goal and starts `action`.
+    fn add_remote(&mut self, action: SyncAction, url: &str) {
+        if url.is_empty() {
+            self.message = Some("No remote repository URL given".to_string());
+            return;
+        }
+        match self.backend.add_remote(&self.task_manager.dir, url) {
+            Ok(()) => self.start_sync(action),
+            Err(e) => self.report_sync_error(action, e),
+        }
+    }
+
+    /// Closes the prompt without running its action.
+    pub fn cancel_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    /// Closes the popup.
+    pub fn close_popup(&mut self) {
+        self.popup = None;
+    }
+
+    /// Starts `action` in the background.
+    ///
+    /// Until it finishes, changes to tasks are kept in memory so they don't race
+    /// with git writing the task files; they are saved, and merged with pulled
+    /// tasks, once the sync is done.
+    fn start_sync(&mut self, action: SyncAction) {
+        if let Err(e) = self.task_manager.save() {
+            if !self.task_manager.conflict {
+                self.report_sync_error(action, e.into());
+            }
+            return;
+        }
+        self.sync_base = Some(self.task_manager.task_list());
+        self.task_manager.defer_saves = true;
+        self.sync = Some(SyncWorker::spawn(
+            action,
+            self.task_manager.dir.clone(),
+            Arc::clone(&self.backend),
+        ));
+    }
+
+    /// Handles background work: applies the result of a finished sync, refreshes the
+    /// repository status after changes and pushes once changes have settled.
+    pub fn tick(&mut self) {
+        self.poll_sync();
+        if self.sync.is_some() || self.is_previewing() {
+            return;
+        }
+        self.reload_changed_tasks();
+        let Some(changed_at) = self.task_manager.changed_at else {
+            return;
+        };
+        if changed_at > self.repo_status_at && self.repo_status_at.elapsed() >= REPO_STATUS_INTERVAL
+        {
+            self.refresh_repo_status();
+        }
+        let delay = self.config.sync.push_after_secs;
+        if delay > 0 && changed_at.elapsed() >= Duration::from_secs(delay) {
+            // Settled edits are handled once, so that a goal without a remote
+            // isn't checked for one on every tick.
+            self.task_manager.changed_at = None;
+            if matches!(self.has_remote(), Ok(true)) {
+                self.start_sync(SyncAction::Push);
+            }
+        }
+    }
+
+    /// Recomputes how the active goal compares to its remote.
+    ///
+    /// Errors are not reported since the status is only informational.
+    pub fn refresh_repo_status(&mut self) {
+        self.repo_status_at = Instant::now();
+        self.repo_status = if self.config.sync.show_status && matches!(self.has_remote(), Ok(true))
+        {
+            self.backend.status(&self.task_manager.dir).ok().flatten()
+        } else {
+            None
+        };
+    }
+
+    /// Applies the result of the background sync if it finished.
+    fn poll_sync(&mut self) {
+        let Some(worker) = &mut self.sync else {
+            return;
+        };
+        if let Some(result) = worker.poll() {
+            let (action, started_at) = (worker.action, worker.started_at);
+            self.sync = None;
+            self.finish_sync(action, started_at, result);
+        }
+    }
+
+    /// Waits for the background sync, if any, and applies its result.
+    pub fn wait_for_sync(&mut self) {
+        if let Some(mut worker) = self.sync.take() {
+            let result = worker.wait();
+            self.finish_sync(worker.action, worker.started_at, result);
+        }
+    }
+
+    fn finish_sync(&mut self, action: SyncAction, started_at: Instant, result: sync::Result<()>) {
+        self.task_manager.defer_saves = false;
+        let base = self.sync_base.take();
+        let edited = self
+            .task_manager
+            .changed_at
+            .is_some_and(|changed_at| changed_at > started_at);
+        // Pulls, and pushes through backends that merge remote changes in, update the tasks file.
+        let saved = match &result {
+            Ok(()) if action == SyncAction::Pull || self.task_manager.changed_on_disk() => {
+                self.load_disk_tasks(base.as_ref().filter(|_| edited))
+            }
+            _ if edited => self.task_manager.save(),
+            _ => Ok(()),
+        };
+        self.refresh_repo_status();
+        let target = self
+            .backend
+            .target(&self.task_manager.dir)
+            .unwrap_or_else(|_| "the remote".to_string());
+        match result.and(saved.map_err(sync::Error::from)) {
+            Ok(()) => {
+                self.last_synced = Some(Local::now());
+                self.message = Some(match action {
+                    SyncAction::Push => format!("Pushed to {}", target),
+                    SyncAction::Pull => format!("Pulled from {}", target),
+                });
+            }
+            Err(e) => self.report_sync_error(action, e),
+        }
+    }
 
-        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
-        Command::new(editor).arg(file.path()).status()?;
+    /// Loads the tasks on disk, merges in changes made since `base`, and keeps the current task.
+    ///
+    /// Without a `base`, i.e. if nothing changed in memory, the tasks on disk are used as is.
+    fn load_disk_tasks(&mut self, base: Option<&TaskList>) -> io::Result<()> {
+        let dir = self.task_manager.dir.clone();
+        let on_disk = persistence::read_tasks(&dir)?;
+        let tasks_list = match base {
+            Some(base) => merge::merge(Some(base), &self.task_manager.task_list(), &on_disk),
+            None => on_disk,
+        };
+        let current_id = self.task_manager.current_task().id;
+        let changed_at = self.task_manager.changed_at;
+        self.task_manager = TaskManager::new(tasks_list, dir)?;
+        self.task_manager.changed_at = changed_at;
+        if !self.task_manager.select(current_id) {
+            self.task_manager.first_undone();
+            self.checklist_index = 0;
+        }
+        let checklist_len = self.task_manager.current_task().checklist.len();
+        self.checklist_index = self.checklist_index.min(checklist_len.saturating_sub(1));
+        match base {
+            Some(_) => self.task_manager.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// Reloads the tasks if another program changed the tasks file, keeping the current task.
+    ///
+    /// Edits are saved right away, so nothing in memory is lost by reloading. Edits
+    /// that could not be saved bring up the conflict prompt instead.
+    fn reload_changed_tasks(&mut self) {
+        if !self.watcher.as_ref().is_some_and(TasksWatcher::changed)
+            || self.task_manager.conflict
+            || !self.task_manager.changed_on_disk()
+        {
+            return;
+        }
+        if self.task_manager.unsaved {
+            self.task_manager.conflict = true;
+            return;
+        }
+        match self.load_disk_tasks(None) {
+            Ok(()) => self.message = Some("Reloaded the tasks changed on disk".to_string()),
+            Err(e) => self.message = Some(format!("Failed to reload the tasks: {}", e)),
+        }
+        self.refresh_repo_status();
+    }
 
-        let content = fs::read_to_string(file.path())?;
-        let task: Task =
-            toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        Ok(Some(task))
+    /// Resolves a refused save by loading the tasks another process wrote, and
+    /// merging in the changes made here since they were loaded if `merge` is true.
+    pub fn resolve_conflict(&mut self, merge: bool) {
+        let base = if merge {
+            self.task_manager.disk_tasks()
+        } else {
+            None
+        };
+        match self.load_disk_tasks(base.as_ref()) {
+            Ok(()) if merge => {
+                self.message = Some("Merged your changes into the tasks on disk".to_string())
+            }
+            Ok(()) => self.message = Some("Reloaded the tasks from disk".to_string()),
+            Err(e) => self.message = Some(format!("Failed to load the tasks: {}", e)),
+        }
+        self.refresh_repo_status();
     }
 
-    /// Pushes tasks to the remote repository.
-    pub fn git_push(&self) -> io::Result<()> {
-        git::init()?;
-        if !git::has_remote()? {
-            let url = self.get_remote_url_from_user()?;
-            git::add_remote(&url)?;
-            self.save_remote_url(&url)?;
+    /// Shows why saving the tasks failed in a popup, unless they changed on disk,
+    /// which the conflict prompt asks about instead.
+    fn check_saved(&mut self, result: io::Result<()>) {
+        if let Err(e) = result {
+            if !self.task_manager.conflict {
+                self.show_error("Saving the tasks failed", e.into());
+            }
         }
-        git::push()
     }
 
-    /// Pulls tasks from the remote repository.
-    pub fn git_pull(&mut self) -> io::Result<()> {
-        git::init()?;
-        if !git::has_remote()? {
-            let url = self.get_remote_url_from_user()?;
-            git::add_remote(&url)?;
-            self.save_remote_url(&url)?;
+    /// Shows a failed sync operation in a popup.
+    pub fn report_sync_error(&mut self, action: SyncAction, error: sync::Error) {
+        self.show_error(&format!("{} failed", action.name()), error);
+    }
+
+    /// Shows `error` in a popup titled `title`.
+    fn show_error(&mut self, title: &str, error: sync::Error) {
+        let body = match error {
+            sync::Error::Git { operation, error } => {
+                format!("$ {}\n\n{}", operation, error.message())
+            }
+            sync::Error::Http {
+                request,
+                status,
+                output,
+            } => format!("{}\nfailed with status {}\n\n{}", request, status, output),
+            e => e.to_string(),
+        };
+        self.popup = Some(Popup {
+            title: title.to_string(),
+            body,
+        });
+    }
+
+    /// Starts a pull if configured to sync on start and a remote exists.
+    fn pull_on_start(&mut self) -> sync::Result<()> {
+        if self.config.sync.pull_on_start && self.has_remote()? {
+            self.start_sync(SyncAction::Pull);
+        }
+        Ok(())
+    }
+
+    /// Pushes if configured to sync on quit, a remote exists and there is anything to push.
+    pub fn push_on_quit(&self) -> sync::Result<()> {
+        if !self.config.sync.push_on_quit || !self.has_remote()? {
+            return Ok(());
+        }
+        let dir = &self.task_manager.dir;
+        match self.backend.status(dir)? {
+            Some(status) if status.ahead == 0 && !status.dirty => Ok(()),
+            _ => self.backend.push(dir, &|_| {}),
+        }
+    }
+
+    fn has_remote(&self) -> sync::Result<bool> {
+        self.backend.has_remote(&self.task_manager.dir)
+    }
+}
+
+/// What to do with the text of a task once the editor exited.
+#[derive(Debug, PartialEq)]
+enum EditResult {
+    /// The user left the text unchanged or emptied it.
+    Cancelled,
+    /// The text holds a task that can be saved.
+    Edited(Task),
+    /// The text is to be edited again; this is the text with the error on top.
+    Retry(String),
+}
+
+/// Decides what to do with `edited`, the text the editor saved for the task with
+/// `id`, given that the editor was opened on `content`.
+///
+/// The task keeps `id`, even if the user changed it, and is edited again if it
+/// doesn't parse in `format` or `check` rejects it.
+fn check_edit(
+    content: &str,
+    edited: &str,
+    id: i32,
+    format: EditFormat,
+    check: impl FnOnce(&Task) -> Result<(), String>,
+) -> EditResult {
+    let body = strip_error_header(edited);
+    if edited == content || body.trim().is_empty() {
+        return EditResult::Cancelled;
+    }
+    let error = match editing::from_text(&body, format) {
+        Ok(mut task) => {
+            task.id = id;
+            task.extract_checklist();
+            match check(&task) {
+                Ok(()) => return EditResult::Edited(task),
+                Err(message) => ParseError {
+                    message,
+                    location: None,
+                },
+            }
         }
-        git::pull()?;
-        self.reload_tasks()
+        Err(e) => e,
+    };
+    EditResult::Retry(with_error_header(&body, error))
+}
+
+/// Prefix of the comment lines explaining why an edited task was rejected.
+const ERROR_PREFIX: &str = "#> ";
+
+/// Returns `content` with a comment on top explaining `error`, a parse error of `content`.
+fn with_error_header(content: &str, error: ParseError) -> String {
+    let mut header: Vec<String> = error.message.lines().map(str::to_string).collect();
+    header.push("Fix the task and save, or empty the file to cancel.".to_string());
+    if let Some((line, column)) = error.location {
+        // The header itself pushes the error down.
+        let line = line + header.len() + 1;
+        header.insert(0, format!("Error at line {}, column {}:", line, column));
+    }
+    let mut result = String::new();
+    for line in header {
+        result.push_str(ERROR_PREFIX);
+        result.push_str(&line);
+        result.push('\n');
     }
+    result + content
+}
+
+/// Removes the comment added by [`with_error_header`] from `content`.
+fn strip_error_header(content: &str) -> String {
+    content
+        .split_inclusive('\n')
+        .skip_while(|line| line.starts_with(ERROR_PREFIX))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: EditFormat = EditFormat::Markdown;
 
-    fn get_remote_url_from_user(&self) -> io::Result<String> {
-        let mut url = String::new();
-        println!("Please enter the remote repository URL:");
-        io::stdin().read_line(&mut url)?;
-        Ok(url.trim().to_string())
+    fn text(task: &Task) -> String {
+        editing::to_text(task, FORMAT).unwrap()
     }
 
-    fn save_remote_url(&self, url: &str) -> io::Result<()> {
-        let path = dirs::home_dir()
-            .expect("Could not find home directory")
-            .join(".tasklings_config");
-        fs::write(path, url)
+    #[test]
+    fn error_headers_are_stripped_again() {
+        let body = "+++\nid = 1\n+++\n\n#> not a header\n";
+        let error = ParseError {
+            message: "invalid key\nexpected `=`".to_string(),
+            location: Some((2, 4)),
+        };
+
+        let content = with_error_header(body, error);
+
+        let header: Vec<&str> = content.lines().take(4).collect();
+        assert_eq!(
+            header,
+            [
+                "#> Error at line 6, column 4:",
+                "#> invalid key",
+                "#> expected `=`",
+                "#> Fix the task and save, or empty the file to cancel.",
+            ]
+        );
+        assert_eq!(content.lines().nth(5), Some("id = 1"));
+        assert_eq!(strip_error_header(&content), body);
+    }
+
+    #[test]
+    fn unchanged_or_emptied_text_cancels() {
+        let content = text(&Task::new(1, "Plan", ""));
+        let check = |_: &Task| Ok(());
+        assert_eq!(
+            check_edit(&content, &content, 1, FORMAT, check),
+            EditResult::Cancelled
+        );
+        assert_eq!(
+            check_edit(&content, " \n", 1, FORMAT, check),
+            EditResult::Cancelled
+        );
+        let emptied = "#> Error at line 4, column 1:\n#> Fix it\n\n";
+        assert_eq!(
+            check_edit(&content, emptied, 1, FORMAT, check),
+            EditResult::Cancelled
+        );
+    }
+
+    #[test]
+    fn edited_tasks_keep_their_id() {
+        let task = Task::new(1, "Plan", "");
+        let content = text(&task);
+        let mut changed = task.clone();
+        changed.id = 7;
+        changed.title = "Plan it".to_string();
+        changed.description = "- [ ] Draft".to_string();
+
+        let result = check_edit(&content, &text(&changed), 1, FORMAT, |_| Ok(()));
+
+        let EditResult::Edited(edited) = result else {
+            panic!("expected an edited task, got {:?}", result);
+        };
+        assert_eq!(edited.id, 1);
+        assert_eq!(edited.title, "Plan it");
+        assert_eq!(edited.checklist.len(), 1);
+    }
+
+    #[test]
+    fn rejected_tasks_are_edited_again() {
+        let task = Task::new(1, "Plan", "");
+        let content = text(&task);
+        let broken = content.replace("title = ", "title ");
+
+        let EditResult::Retry(retry) = check_edit(&content, &broken, 1, FORMAT, |_| Ok(())) else {
+            panic!("expected a retry");
+        };
+        assert!(retry.starts_with("#> Error at line "));
+        assert_eq!(strip_error_header(&retry), broken);
+
+        let mut done = task.clone();
+        done.done = true;
+        let check = |_: &Task| Err("Task #1 is blocked".to_string());
+        let EditResult::Retry(retry) = check_edit(&content, &text(&done), 1, FORMAT, check) else {
+            panic!("expected a retry");
+        };
+        assert!(retry.starts_with("#> Task #1 is blocked\n"));
+        assert_eq!(strip_error_header(&retry), text(&done));
+
+        // Saving the text with the error unchanged cancels.
+        assert_eq!(
+            check_edit(&retry, &retry, 1, FORMAT, |_| Ok(())),
+            EditResult::Cancelled
+        );
     }
 }
@@ -0,0 +1,148 @@
+//! Local snapshots of tasks files, to recover from a corrupted file or a bad edit.
+
+use crate::config::BackupConfig;
+use crate::persistence;
+use chrono::{Days, Local, NaiveDateTime};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory inside the data directory holding the snapshots of each goal.
+const BACKUP_DIR: &str = ".backups";
+
+/// Format of snapshot file names, which sort chronologically.
+const NAME_FORMAT: &str = "%Y-%m-%dT%H-%M-%S";
+
+/// Name of the copy of a broken tasks file kept when a snapshot is restored.
+pub const BROKEN_FILE: &str = "tasks.toml.broken";
+
+/// A snapshot of the tasks file of a goal.
+pub struct Backup {
+    /// Path of the snapshot.
+    pub path: PathBuf,
+    /// When the snapshot was taken.
+    pub taken_at: NaiveDateTime,
+}
+
+/// Returns the directory holding the snapshots of `goal`.
+fn backup_dir(data_dir: &Path, goal: &str) -> PathBuf {
+    data_dir.join(BACKUP_DIR).join(goal)
+}
+
+/// Lists the snapshots of `goal`, newest first.
+pub fn list(data_dir: &Path, goal: &str) -> io::Result<Vec<Backup>> {
+    let dir = backup_dir(data_dir, goal);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<Backup> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = path.file_name()?.to_str()?.strip_suffix(".toml")?;
+            let taken_at = NaiveDateTime::parse_from_str(name, NAME_FORMAT).ok()?;
+            Some(Backup { path, taken_at })
+        })
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.taken_at));
+    Ok(backups)
+}
+
+/// Snapshots the tasks file of `goal` unless it is unchanged since the last
+/// snapshot, then deletes the snapshots `config` doesn't keep.
+///
+/// The goal is locked so that the snapshot never catches a save halfway.
+pub fn snapshot(data_dir: &Path, goal: &str, config: &BackupConfig) -> io::Result<()> {
+    if config.keep == 0 {
+        return Ok(());
+    }
+    let dir = persistence::get_goal_dir(data_dir, goal);
+    let _lock = persistence::lock_goal(&dir)?;
+    let Some(content) = persistence::read_tasks_file(&dir)? else {
+        return Ok(());
+    };
+    let mut backups = list(data_dir, goal)?;
+    let latest = backups
+        .first()
+        .map(|backup| fs::read_to_string(&backup.path))
+        .transpose()?;
+    if latest.as_ref() != Some(&content) {
+        let dir = backup_dir(data_dir, goal);
+        fs::create_dir_all(&dir)?;
+        let taken_at = Local::now().naive_local();
+        let path = dir.join(format!("{}.toml", taken_at.format(NAME_FORMAT)));
+        persistence::write_atomic(&path, &content)?;
+        backups.insert(0, Backup { path, taken_at });
+    }
+    prune(&backups, config)
+}
+
+/// Deletes the snapshots beyond the retention of `config`, always keeping the newest.
+fn prune(backups: &[Backup], config: &BackupConfig) -> io::Result<()> {
+    let cutoff = Some(config.max_age_days)
+        .filter(|&days| days > 0)
+        .and_then(|days| Local::now().naive_local().checked_sub_days(Days::new(days)));
+    for (i, backup) in backups.iter().enumerate().skip(1) {
+        let expired = cutoff.is_some_and(|cutoff| backup.taken_at < cutoff);
+        if i >= config.keep || expired {
+            fs::remove_file(&backup.path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the tasks file of `goal` with its newest snapshot that can be
+/// read, and returns that snapshot, or `None` if there is none.
+///
+/// The replaced file is kept next to it as [`BROKEN_FILE`].
+pub fn restore_latest(data_dir: &Path, goal: &str) -> io::Result<Option<Backup>> {
+    for backup in list(data_dir, goal)? {
+        let content = fs::read_to_string(&backup.path)?;
+        if persistence::parse_tasks(&content).is_err() {
+            continue;
+        }
+        let dir = persistence::get_goal_dir(data_dir, goal);
+        let _lock = persistence::lock_goal(&dir)?;
+        let path = persistence::get_tasks_file(&dir);
+        if path.exists() {
+            fs::copy(&path, dir.join(BROKEN_FILE))?;
+        }
+        persistence::write_tasks_file(&dir, &content)?;
+        return Ok(Some(backup));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn snapshots_only_changed_files_and_restores_them() {
+        let data_dir = TempDir::new().unwrap();
+        let dir = persistence::get_goal_dir(data_dir.path(), "work");
+        fs::create_dir_all(&dir).unwrap();
+        let content = "tasks = []\n";
+        persistence::write_tasks_file(&dir, content).unwrap();
+        let config = BackupConfig::default();
+
+        snapshot(data_dir.path(), "work", &config).unwrap();
+        snapshot(data_dir.path(), "work", &config).unwrap();
+        let backups = list(data_dir.path(), "work").unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0].path).unwrap(), content);
+
+        persistence::write_tasks_file(&dir, "tasks = [").unwrap();
+        let restored = restore_latest(data_dir.path(), "work").unwrap().unwrap();
+        assert_eq!(restored.path, backups[0].path);
+        assert_eq!(
+            persistence::read_tasks_file(&dir).unwrap().unwrap(),
+            content
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join(BROKEN_FILE)).unwrap(),
+            "tasks = ["
+        );
+    }
+}
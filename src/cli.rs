@@ -0,0 +1,484 @@
+//! Non-interactive command line interface.
+//!
+//! Every subcommand exits with status 0 on success and 1 on failure; usage
+//! errors exit with status 2.
+
+use crate::app;
+use crate::config::Config;
+use crate::crypto;
+use crate::onboarding::{self, Setup};
+use crate::persistence;
+use crate::sync::{self, SyncBackend};
+use crate::tasks::{Task, TaskManager};
+use chrono::{Local, NaiveDate};
+use clap::{ArgGroup, Parser, Subcommand};
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+/// Your terminal long term goal tracker.
+///
+/// Starts the interactive UI when no subcommand is given.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Configuration file to use instead of the default one.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Goal to use instead of the last active one.
+    #[arg(short, long, global = true)]
+    pub goal: Option<String>,
+    /// Print machine-readable JSON instead of text.
+    #[arg(long, global = true)]
+    pub json: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[command(flatten)]
+    Tasks(TaskCommand),
+    /// List all goals.
+    Goals,
+    /// Push tasks to the configured sync backend.
+    Push {
+        /// Remote git repository URL to add if none is configured yet.
+        #[arg(long)]
+        remote: Option<String>,
+    },
+    /// Pull tasks from the configured sync backend.
+    Pull {
+        /// Remote git repository URL to add if none is configured yet.
+        #[arg(long)]
+        remote: Option<String>,
+    },
+    /// Set up a goal, e.g. on a new machine, and make it the active goal.
+    #[command(group(ArgGroup::new("setup").required(true).args(["clone", "name", "demo"])))]
+    Init {
+        /// Clone an existing tasks repository into the goal (default: "tasks").
+        #[arg(long, value_name = "URL")]
+        clone: Option<String>,
+        /// Start a blank goal with this title.
+        #[arg(long, value_name = "TITLE")]
+        name: Option<String>,
+        /// Start the goal (default: "tasks") with the demo tasks.
+        #[arg(long)]
+        demo: bool,
+    },
+    /// Create an age identity for encrypting tasks at rest.
+    Keygen {
+        /// Where to write the identity (default: the configured identity, or
+        /// identity.txt next to the configuration file).
+        path: Option<PathBuf>,
+    },
+}
+
+/// Subcommands working on the tasks of a goal.
+#[derive(Subcommand, Debug)]
+pub enum TaskCommand {
+    /// Add a new task.
+    Add {
+        /// Title of the task.
+        title: String,
+        /// Description of the task.
+        #[arg(short, long, default_value = "", allow_hyphen_values = true)]
+        description: String,
+        /// Ids of tasks that must be done first, separated by commas.
+        #[arg(long, value_delimiter = ',')]
+        depends_on: Vec<i32>,
+        /// Date by which the task should be done (YYYY-MM-DD).
+        #[arg(long)]
+        due: Option<NaiveDate>,
+        /// Date before which the task is not started (YYYY-MM-DD).
+        #[arg(long)]
+        start_after: Option<NaiveDate>,
+    },
+    /// Mark a task as done.
+    Done {
+        /// Id of the task.
+        id: i32,
+    },
+    /// Mark a task as not done.
+    Undone {
+        /// Id of the task.
+        id: i32,
+    },
+    /// List tasks.
+    List {
+        /// Only list tasks that are not done.
+        #[arg(long)]
+        undone: bool,
+    },
+    /// Show a task in detail.
+    Show {
+        /// Id of the task; defaults to the current task.
+        id: Option<i32>,
+    },
+    /// Show the task to work on next.
+    Current,
+    /// Show the progress of the goal.
+    Status,
+}
+
+/// A task together with its derived state.
+#[derive(Serialize)]
+struct TaskView<'a> {
+    #[serde(flatten)]
+    task: &'a Task,
+    blocked_by: Vec<i32>,
+    overdue: bool,
+}
+
+/// The progress of a goal.
+#[derive(Serialize)]
+struct Status {
+    goal: String,
+    title: String,
+    done: usize,
+    total: usize,
+    percent: f64,
+    overdue: usize,
+    current_streak: u32,
+    longest_streak: u32,
+    target_date: Option<NaiveDate>,
+    days_remaining: Option<i64>,
+}
+
+/// Runs `command` on `goal`, or on the last active goal if `None`.
+pub fn run(command: Command, config: &Config, goal: Option<String>, json: bool) -> ExitCode {
+    let Err(e) = execute(command, config, goal, json) else {
+        return ExitCode::SUCCESS;
+    };
+    if json {
+        let error = serde_json::Value::String(e.to_string());
+        println!("{{\"ok\":false,\"error\":{}}}", error);
+    } else {
+        eprintln!("error: {}", e);
+    }
+    ExitCode::FAILURE
+}
+
+/// Sets up a goal and reports its name.
+fn init(config: &Config, setup: &Setup, goal: Option<&str>, json: bool) -> io::Result<()> {
+    let goal = onboarding::apply(&config.data_dir, setup, goal)?;
+    if json {
+        return print_json(&serde_json::json!({ "goal": goal }));
+    }
+    println!("Initialized goal '{}'", goal);
+    Ok(())
+}
+
+fn keygen(config: &Config, path: Option<PathBuf>, json: bool) -> io::Result<()> {
+    let path = path
+        .or_else(|| config.encryption.identity.clone())
+        .unwrap_or_else(|| Config::default_path().with_file_name("identity.txt"));
+    let public_key = crypto::generate(&path)?;
+    if json {
+        return print_json(&serde_json::json!({ "path": path, "public_key": public_key }));
+    }
+    println!("Created {}", path.display());
+    println!("Public key: {}", public_key);
+    if config.encryption.identity.as_ref() != Some(&path) {
+        println!();
+        println!("To encrypt tasks with it, add to the configuration:");
+        println!("[encryption]");
+        println!("identity = {:?}", path.display().to_string());
+    }
+    Ok(())
+}
+
+fn execute(command: Command, config: &Config, goal: Option<String>, json: bool) -> io::Result<()> {
+    if let Some(goal) = &goal {
+        persistence::validate_goal_name(goal)?;
+    }
+    let active_goal = || {
+        goal.clone()
+            .unwrap_or_else(|| persistence::load_active_goal(&config.data_dir))
+    };
+    match command {
+        Command::Init { clone, name, demo } => {
+            let setup = match (clone, name, demo) {
+                (Some(url), _, _) => Setup::Clone(url),
+                (None, Some(title), _) => Setup::Blank(title),
+                (None, None, true) => Setup::Demo,
+                // The `setup` argument group requires one of the three.
+                (None, None, false) => unreachable!(),
+            };
+            init(config, &setup, goal.as_deref(), json)
+        }
+        Command::Goals => {
+            let goal = active_goal();
+            let goals = app::goal_entries(&config.data_dir)?;
+            if json {
+                return print_json(&goals);
+            }
+            for entry in goals {
+                let marker = if entry.name == goal { "*" } else { " " };
+                println!(
+                    "{} {} - {} ({}/{} done)",
+                    marker, entry.name, entry.title, entry.done, entry.total
+                );
+            }
+            Ok(())
+        }
+        Command::Keygen { path } => keygen(config, path, json),
+        Command::Push { remote } => {
+            let dir = persistence::existing_goal_dir(&config.data_dir, &active_goal())?;
+            let backend = prepare_remote(&dir, config, remote)?;
+            backend.push(&dir, &|_| {})?;
+            print_ok(json)
+        }
+        Command::Pull { remote } => {
+            let dir = persistence::existing_goal_dir(&config.data_dir, &active_goal())?;
+            let backend = prepare_remote(&dir, config, remote)?;
+            backend.pull(&dir, &|_| {})?;
+            print_ok(json)
+        }
+        Command::Tasks(command) => {
+            let goal = active_goal();
+            let dir = persistence::existing_goal_dir(&config.data_dir, &goal)?;
+            let mut task_manager = TaskManager::new(persistence::read_tasks(&dir)?, dir)?;
+            execute_on_tasks(command, &goal, &mut task_manager, json)
+        }
+    }
+}
+
+fn execute_on_tasks(
+    command: TaskCommand,
+    goal: &str,
+    task_manager: &mut TaskManager,
+    json: bool,
+) -> io::Result<()> {
+    match command {
+        TaskCommand::Add {
+            title,
+            description,
+            depends_on,
+            due,
+            start_after,
+        } => {
+            let mut task = Task::new(task_manager.next_id(), title, description);
+            task.depends_on = depends_on;
+            task.due = due;
+            task.start_after = start_after;
+            task.extract_checklist();
+            let id = task.id;
+            task_manager.add_task(task)?;
+            select(task_manager, id)?;
+            print_task(task_manager, json)
+        }
+        TaskCommand::Done { id } => {
+            select(task_manager, id)?;
+            if !task_manager.current_task().done {
+                task_manager.mark_done()?;
+            }
+            print_task(task_manager, json)
+        }
+        TaskCommand::Undone { id } => {
+            select(task_manager, id)?;
+            task_manager.mark_undone()?;
+            print_task(task_manager, json)
+        }
+        TaskCommand::List { undone } => {
+            let today = Local::now().date_naive();
+            let views: Vec<TaskView> = (0..task_manager.tasks.len())
+                .filter(|&i| !undone || !task_manager.tasks[i].done)
+                .map(|i| task_view(task_manager, i))
+                .collect();
+            if json {
+                return print_json(&views);
+            }
+            for view in views {
+                let mark = if view.task.done { "[x]" } else { "[ ]" };
+                let mut notes = Vec::new();
+                if !view.task.done && !view.blocked_by.is_empty() {
+                    notes.push("blocked");
+                }
+                if view.overdue {
+                    notes.push("overdue");
+                }
+                if !view.task.is_started(today) {
+                    notes.push("not started");
+                }
+                let notes = if notes.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", notes.join(", "))
+                };
+                println!("{} #{} {}{}", mark, view.task.id, view.task.title, notes);
+            }
+            Ok(())
+        }
+        TaskCommand::Show { id } => {
+            match id {
+                Some(id) => select(task_manager, id)?,
+                None if task_manager.tasks.is_empty() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("No tasks in goal '{}'", goal),
+                    ));
+                }
+                None => task_manager.first_undone(),
+            }
+            print_task(task_manager, json)
+        }
+        TaskCommand::Current => {
+            task_manager.first_undone();
+            if task_manager.is_done() {
+                if json {
+                    return print_json(&None::<TaskView>);
+                }
+                println!("All tasks done!");
+                return Ok(());
+            }
+            if json {
+                return print_task(task_manager, json);
+            }
+            let task = task_manager.current_task();
+            println!("#{} {}", task.id, task.title);
+            Ok(())
+        }
+        TaskCommand::Status => {
+            let today = Local::now().date_naive();
+            let (current_streak, longest_streak) = task_manager.streaks(today);
+            let status = Status {
+                goal: goal.to_string(),
+                title: task_manager.the_goal.clone(),
+                done: task_manager.tasks.iter().filter(|t| t.done).count(),
+                total: task_manager.tasks.len(),
+                percent: task_manager.progress() * 100.0,
+                overdue: task_manager
+                    .tasks
+                    .iter()
+                    .filter(|t| t.is_overdue(today))
+                    .count(),
+                current_streak,
+                longest_streak,
+                target_date: task_manager.target_date,
+                days_remaining: task_manager.days_remaining(),
+            };
+            if json {
+                return print_json(&status);
+            }
+            println!("Goal: {} ({})", status.title, status.goal);
+            println!(
+                "Progress: {}/{} done ({:.0}%)",
+                status.done, status.total, status.percent
+            );
+            println!(
+                "Streak: {} {} (best: {})",
+                status.current_streak,
+                if status.current_streak == 1 {
+                    "day"
+                } else {
+                    "days"
+                },
+                status.longest_streak
+            );
+            if status.overdue > 0 {
+                println!("Overdue: {}", status.overdue);
+            }
+            if let (Some(target), Some(days)) = (status.target_date, status.days_remaining) {
+                println!("Deadline: {} ({} days left)", target, days);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Moves to the task with the given id or fails with `NotFound`.
+fn select(task_manager: &mut TaskManager, id: i32) -> io::Result<()> {
+    if task_manager.select(id) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No task with id {}", id),
+        ))
+    }
+}
+
+fn task_view(task_manager: &TaskManager, index: usize) -> TaskView<'_> {
+    let task = &task_manager.tasks[index];
+    TaskView {
+        task,
+        blocked_by: task_manager.blockers(index).iter().map(|t| t.id).collect(),
+        overdue: task.is_overdue(Local::now().date_naive()),
+    }
+}
+
+/// Prints the current task in detail.
+fn print_task(task_manager: &TaskManager, json: bool) -> io::Result<()> {
+    let view = task_view(task_manager, task_manager.current_index);
+    if json {
+        return print_json(&view);
+    }
+    let task = view.task;
+    let state = if task.done { "done" } else { "undone" };
+    println!("#{} {} [{}]", task.id, task.title, state);
+    if let Some(due) = task.due {
+        let overdue = if view.overdue { " (overdue)" } else { "" };
+        println!("Due: {}{}", due, overdue);
+    }
+    if let Some(start) = task.start_after {
+        println!("Starts: {}", start);
+    }
+    if !task.done && !view.blocked_by.is_empty() {
+        let ids: Vec<String> = view
+            .blocked_by
+            .iter()
+            .map(|id| format!("#{}", id))
+            .collect();
+        println!("Blocked by: {}", ids.join(", "));
+    }
+    if !task.description.is_empty() {
+        println!();
+        println!("{}", task.description);
+    }
+    if !task.checklist.is_empty() {
+        println!();
+        for item in &task.checklist {
+            let mark = if item.done { "[x]" } else { "[ ]" };
+            println!("{} {}", mark, item.title);
+        }
+    }
+    Ok(())
+}
+
+fn print_json<T: Serialize>(value: &T) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn print_ok(json: bool) -> io::Result<()> {
+    if json {
+        println!("{{\"ok\":true}}");
+    }
+    Ok(())
+}
+
+/// Returns the configured sync backend after making sure it has a remote.
+fn prepare_remote(
+    dir: &Path,
+    config: &Config,
+    remote: Option<String>,
+) -> io::Result<Arc<dyn SyncBackend>> {
+    let backend = sync::backend(config);
+    if backend.has_remote(dir)? {
+        return Ok(backend);
+    }
+    let message = match (remote, backend.remote_prompt()) {
+        (Some(url), Some(_)) => {
+            backend.add_remote(dir, &url)?;
+            return Ok(backend);
+        }
+        (None, Some(_)) => "No remote repository configured; pass --remote <url>",
+        (_, None) => "No sync location configured; set it in the configuration file",
+    };
+    Err(io::Error::new(io::ErrorKind::NotFound, message))
+}
@@ -0,0 +1,347 @@
+//! User configuration.
+
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the data directory.
+const HOME_ENV: &str = "TASKLINGS_HOME";
+
+/// Application configuration, read from `config.toml`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Directory holding one subdirectory per goal.
+    pub data_dir: PathBuf,
+    /// Command used to edit tasks, falling back to `$VISUAL`, `$EDITOR` and `nano`.
+    pub editor: Option<String>,
+    /// Format tasks are edited in.
+    pub edit_format: EditFormat,
+    /// Git settings.
+    pub git: GitConfig,
+    /// Shared folder settings, used by the `folder` sync backend.
+    pub folder: FolderConfig,
+    /// WebDAV settings, used by the `webdav` sync backend.
+    pub webdav: WebDavConfig,
+    /// Encryption of the tasks file at rest.
+    pub encryption: EncryptionConfig,
+    /// Local snapshots of the tasks file.
+    pub backup: BackupConfig,
+    /// Automatic synchronization settings.
+    pub sync: SyncConfig,
+    /// Colors of the user interface.
+    pub theme: Theme,
+    /// Keys bound to each action, replacing the default keys of that action.
+    pub keybindings: BTreeMap<String, Vec<String>>,
+}
+
+/// Git settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct GitConfig {
+    /// Name of the remote to push to and pull from, defaulting to the upstream
+    /// remote of the current branch, then `origin`.
+    pub remote: Option<String>,
+    /// Remote branch to push to and pull from, defaulting to the current branch.
+    pub branch: Option<String>,
+}
+
+/// Shared folder settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct FolderConfig {
+    /// Folder holding one subdirectory per goal, e.g. a Syncthing or Dropbox folder.
+    pub path: Option<PathBuf>,
+}
+
+/// WebDAV settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct WebDavConfig {
+    /// URL of the collection holding one collection per goal.
+    pub url: Option<String>,
+    /// User name for basic authentication; no authentication if `None`.
+    pub username: Option<String>,
+    /// Password for basic authentication.
+    pub password: Option<String>,
+}
+
+/// Encryption of the tasks file at rest.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// age identity file to encrypt tasks to, e.g. created by `tasklings keygen`;
+    /// tasks are stored in plain text if `None`.
+    pub identity: Option<PathBuf>,
+}
+
+/// Local snapshots of the tasks file, taken when a goal is opened in the TUI.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Number of snapshots kept per goal; 0 disables backups.
+    pub keep: usize,
+    /// Delete snapshots older than this many days, keeping at least the newest; 0 keeps them regardless of age.
+    pub max_age_days: u64,
+}
+
+/// Format tasks are edited in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EditFormat {
+    /// Markdown with the description as the body and the other fields in TOML front matter.
+    #[default]
+    Markdown,
+    /// The task as a TOML table, as stored in `tasks.toml`.
+    Toml,
+}
+
+/// Where goals are synced to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A remote git repository per goal.
+    #[default]
+    Git,
+    /// A shared folder, see [`FolderConfig`].
+    Folder,
+    /// A WebDAV server, see [`WebDavConfig`].
+    WebDav,
+}
+
+/// Automatic synchronization settings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Where goals are synced to.
+    pub backend: Backend,
+    /// Pull from the remote when the application starts.
+    pub pull_on_start: bool,
+    /// Push to the remote when the application quits, if there is anything to push.
+    pub push_on_quit: bool,
+    /// Push in the background once tasks have not changed for this many seconds; 0 disables it.
+    pub push_after_secs: u64,
+    /// Show how many commits the goal is ahead of and behind the remote in the header.
+    pub show_status: bool,
+}
+
+/// Colors of the user interface.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Theme {
+    /// Color of progress and completed items.
+    pub accent: Color,
+    /// Color of overdue and blocked tasks.
+    pub danger: Color,
+    /// Color of messages.
+    pub warning: Color,
+    /// Color of secondary information.
+    pub muted: Color,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_dir: dirs::home_dir()
+                .expect("Could not find home directory")
+                .join(".tasks"),
+            editor: None,
+            edit_format: EditFormat::default(),
+            git: GitConfig::default(),
+            folder: FolderConfig::default(),
+            webdav: WebDavConfig::default(),
+            encryption: EncryptionConfig::default(),
+            backup: BackupConfig::default(),
+            sync: SyncConfig::default(),
+            theme: Theme::default(),
+            keybindings: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            keep: 10,
+            max_age_days: 0,
+        }
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            backend: Backend::Git,
+            pull_on_start: false,
+            push_on_quit: false,
+            push_after_secs: 0,
+            show_status: true,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            accent: Color::LightGreen,
+            danger: Color::LightRed,
+            warning: Color::Yellow,
+            muted: Color::DarkGray,
+        }
+    }
+}
+
+impl Config {
+    /// Returns the default path of the configuration file.
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| {
+                dirs::home_dir()
+                    .expect("Could not find home directory")
+                    .join(".config")
+            })
+            .join("tasklings")
+            .join("config.toml")
+    }
+
+    /// Loads the configuration from `path`, or from the default path if `None`.
+    ///
+    /// A missing file yields the default configuration. `TASKLINGS_HOME`
+    /// overrides the data directory.
+    pub fn load(path: Option<&Path>) -> io::Result<Config> {
+        Self::load_with_home(path, env::var_os(HOME_ENV))
+    }
+
+    /// Loads the configuration like [`Config::load`], with `home` as the value of
+    /// `TASKLINGS_HOME`.
+    fn load_with_home(path: Option<&Path>, home: Option<OsString>) -> io::Result<Config> {
+        let path = path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(Self::default_path);
+        let mut config: Config = if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            toml::from_str(&content).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?
+        } else {
+            Config::default()
+        };
+        if let Some(home) = home.filter(|home| !home.is_empty()) {
+            config.data_dir = PathBuf::from(home);
+        }
+        config.data_dir = expand_home(&config.data_dir);
+        config.folder.path = config.folder.path.as_deref().map(expand_home);
+        config.encryption.identity = config.encryption.identity.as_deref().map(expand_home);
+        Ok(config)
+    }
+
+    /// Returns the editor command split into the program and its arguments.
+    pub fn editor_command(&self) -> Vec<String> {
+        let editor = self
+            .editor
+            .clone()
+            .or_else(|| env::var("VISUAL").ok())
+            .or_else(|| env::var("EDITOR").ok())
+            .filter(|editor| !editor.trim().is_empty())
+            .unwrap_or_else(|| "nano".to_string());
+        editor.split_whitespace().map(str::to_string).collect()
+    }
+}
+
+/// Replaces a leading `~` in `path` with the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(rest) => dirs::home_dir()
+            .expect("Could not find home directory")
+            .join(rest),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{InputEvent, Keymap};
+    use crossterm::event::{KeyCode, KeyModifiers};
+    use tempfile::TempDir;
+
+    /// Writes `content` as a configuration file and loads it.
+    fn load(content: &str, home: Option<&str>) -> Config {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, content).unwrap();
+        Config::load_with_home(Some(&path), home.map(OsString::from)).unwrap()
+    }
+
+    #[test]
+    fn leading_tildes_expand_to_the_home_directory() {
+        let home = dirs::home_dir().unwrap();
+        let config = load(
+            r#"
+            data_dir = "~/notes/tasks"
+            [folder]
+            path = "~/Sync"
+            [encryption]
+            identity = "/keys/~/identity.txt"
+            "#,
+            None,
+        );
+        assert_eq!(config.data_dir, home.join("notes/tasks"));
+        assert_eq!(config.folder.path, Some(home.join("Sync")));
+        assert_eq!(
+            config.encryption.identity,
+            Some(PathBuf::from("/keys/~/identity.txt"))
+        );
+    }
+
+    #[test]
+    fn home_variable_overrides_the_data_directory() {
+        let content = r#"data_dir = "/srv/tasks""#;
+        assert_eq!(load(content, None).data_dir, Path::new("/srv/tasks"));
+        assert_eq!(load(content, Some("")).data_dir, Path::new("/srv/tasks"));
+        assert_eq!(
+            load(content, Some("/tmp/tasks")).data_dir,
+            Path::new("/tmp/tasks")
+        );
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(load(content, Some("~/t")).data_dir, home.join("t"));
+    }
+
+    #[test]
+    fn keybindings_override_the_default_keys() {
+        let config = load(
+            r#"
+            [keybindings]
+            mark_done = ["D", "Ctrl+d"]
+            "#,
+            None,
+        );
+        let keymap = Keymap::new(&config.keybindings).unwrap();
+        let event = |code| keymap.event(code, KeyModifiers::NONE);
+        assert_eq!(event(KeyCode::Char('D')), InputEvent::MarkDone);
+        assert_eq!(event(KeyCode::Char('d')), InputEvent::Noop);
+        assert_eq!(
+            keymap.event(KeyCode::Char('d'), KeyModifiers::CONTROL),
+            InputEvent::MarkDone
+        );
+        assert_eq!(event(KeyCode::Char('u')), InputEvent::MarkUndone);
+    }
+
+    #[test]
+    fn missing_file_yields_the_defaults() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let config = Config::load_with_home(Some(&path), None).unwrap();
+        assert_eq!(config.edit_format, EditFormat::Markdown);
+        assert_eq!(config.backup.keep, 10);
+        assert!(config.keybindings.is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+//! Optional encryption of the tasks file at rest, using age.
+//!
+//! Once an identity is unlocked, tasks files are written ASCII-armored and
+//! encrypted to it, so only ciphertext reaches the goal's remote. Reading
+//! accepts both encrypted and plain files, which lets existing goals switch
+//! to encryption on their next save.
+
+use crate::config::EncryptionConfig;
+use age::secrecy::{ExposeSecret, SecretString};
+use age::{scrypt, x25519};
+use chrono::Local;
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// First line of an ASCII-armored age file.
+const ARMOR_HEADER: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Start of a binary age file.
+const BINARY_HEADER: &[u8] = b"age-encryption.org/";
+
+/// Environment variable holding the passphrase of a protected identity file.
+pub const PASSPHRASE_ENV: &str = "TASKLINGS_PASSPHRASE";
+
+/// The identity tasks files are encrypted to, once unlocked.
+static IDENTITY: OnceLock<x25519::Identity> = OnceLock::new();
+
+/// Loads the identity configured in `config`, if any, and encrypts tasks files from now on.
+///
+/// If the identity file is itself encrypted with a passphrase, e.g. by
+/// `age --passphrase`, the passphrase is read from `TASKLINGS_PASSPHRASE`,
+/// or else asked for with `passphrase`.
+pub fn unlock(
+    config: &EncryptionConfig,
+    passphrase: impl FnOnce() -> io::Result<String>,
+) -> io::Result<()> {
+    let Some(path) = &config.identity else {
+        return Ok(());
+    };
+    let content = fs::read(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    let content =
+        if is_encrypted(&String::from_utf8_lossy(&content)) || content.starts_with(BINARY_HEADER) {
+            let passphrase = match env::var(PASSPHRASE_ENV) {
+                Ok(passphrase) => passphrase,
+                Err(_) => passphrase()?,
+            };
+            let identity = scrypt::Identity::new(SecretString::from(passphrase));
+            age::decrypt(&identity, &content).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("Could not unlock {}: {}", path.display(), e),
+                )
+            })?
+        } else {
+            content
+        };
+    let identity = String::from_utf8_lossy(&content)
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+        .and_then(|line| line.parse::<x25519::Identity>().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} holds no age identity", path.display()),
+            )
+        })?;
+    let _ = IDENTITY.set(identity);
+    Ok(())
+}
+
+/// Creates a new identity file at `path` and returns its public key.
+pub fn generate(path: &Path) -> io::Result<String> {
+    if path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let identity = x25519::Identity::generate();
+    let public = identity.to_public().to_string();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    writeln!(file, "# created: {}", Local::now().to_rfc3339())?;
+    writeln!(file, "# public key: {}", public)?;
+    writeln!(file, "{}", identity.to_string().expose_secret())?;
+    Ok(public)
+}
+
+/// Returns `true` if tasks files are encrypted when written.
+pub fn is_enabled() -> bool {
+    IDENTITY.get().is_some()
+}
+
+/// Returns `true` if `content` is an ASCII-armored age file.
+pub fn is_encrypted(content: &str) -> bool {
+    content.trim_start().starts_with(ARMOR_HEADER)
+}
+
+/// Encrypts the content of a tasks file if encryption is enabled.
+pub fn encrypt(plaintext: &str) -> io::Result<String> {
+    match IDENTITY.get() {
+        Some(identity) => age::encrypt_and_armor(&identity.to_public(), plaintext.as_bytes())
+            .map_err(io::Error::other),
+        None => Ok(plaintext.to_string()),
+    }
+}
+
+/// Decrypts the content of a tasks file if it is encrypted.
+pub fn decrypt(content: &str) -> io::Result<Cow<'_, str>> {
+    if !is_encrypted(content) {
+        return Ok(Cow::Borrowed(content));
+    }
+    let Some(identity) = IDENTITY.get() else {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "The tasks are encrypted; set `identity` in the [encryption] section of the configuration",
+        ));
+    };
+    let plaintext = age::decrypt(identity, content.as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    String::from_utf8(plaintext)
+        .map(Cow::Owned)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,161 @@
+//! Conversion of tasks to and from the text edited in an external editor.
+
+use crate::config::EditFormat;
+use crate::persistence::ParseError;
+use crate::tasks::Task;
+use std::io;
+
+/// Line delimiting the TOML front matter of a Markdown task.
+const FRONT_MATTER: &str = "+++";
+
+/// Returns the extension of files in `format`, so that editors highlight them.
+pub fn extension(format: EditFormat) -> &'static str {
+    match format {
+        EditFormat::Markdown => ".md",
+        EditFormat::Toml => ".toml",
+    }
+}
+
+/// Writes `task` in `format`.
+///
+/// In Markdown, the description and the checklist, as `- [ ]` lines, form the
+/// body, and the other fields the front matter.
+pub fn to_text(task: &Task, format: EditFormat) -> io::Result<String> {
+    if format == EditFormat::Toml {
+        return toml::to_string_pretty(task).map_err(io::Error::other);
+    }
+    let mut front_matter = toml::Table::try_from(task).map_err(io::Error::other)?;
+    front_matter.remove("description");
+    front_matter.remove("checklist");
+    let front_matter = toml::to_string_pretty(&front_matter).map_err(io::Error::other)?;
+    let mut body = task.description.clone();
+    if !task.checklist.is_empty() {
+        if !body.is_empty() {
+            body.push_str("\n\n");
+        }
+        let items: Vec<String> = task
+            .checklist
+            .iter()
+            .map(|item| format!("- [{}] {}", if item.done { "x" } else { " " }, item.title))
+            .collect();
+        body.push_str(&items.join("\n"));
+    }
+    Ok(format!(
+        "{}\n{}{}\n\n{}\n",
+        FRONT_MATTER, front_matter, FRONT_MATTER, body
+    ))
+}
+
+/// Reads a task written in `format`.
+///
+/// Checklist lines are left in the description; see [`Task::extract_checklist`].
+pub fn from_text(content: &str, format: EditFormat) -> Result<Task, ParseError> {
+    if format == EditFormat::Toml {
+        return toml::from_str(content).map_err(|e| ParseError::new(content, e));
+    }
+    let mut lines = content.split_inclusive('\n');
+    if lines.next().map(str::trim_end) != Some(FRONT_MATTER) {
+        return Err(ParseError {
+            message: format!(
+                "expected a `{}` line starting the front matter",
+                FRONT_MATTER
+            ),
+            location: Some((1, 1)),
+        });
+    }
+    let mut front_matter = String::new();
+    let mut closed = false;
+    for line in lines.by_ref() {
+        if line.trim_end() == FRONT_MATTER {
+            closed = true;
+            break;
+        }
+        front_matter.push_str(line);
+    }
+    if !closed {
+        return Err(ParseError {
+            message: format!("expected a `{}` line ending the front matter", FRONT_MATTER),
+            location: None,
+        });
+    }
+    let body: String = lines.collect();
+    // The description goes first, so that errors in the front matter keep their
+    // place relative to each other.
+    let description = format!(
+        "description = {}\n",
+        toml::Value::String(body.trim().to_string())
+    );
+    let source = description.clone() + &front_matter;
+    toml::from_str(&source).map_err(|e| {
+        let mut error = ParseError::new(&source, e);
+        // Count the opening `+++` line instead of the description.
+        let shift = description.lines().count();
+        error.location = error
+            .location
+            .map(|(line, column)| ((line + 1).saturating_sub(shift).max(1), column));
+        error
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::ChecklistItem;
+    use chrono::NaiveDate;
+
+    fn task() -> Task {
+        let mut task = Task::new(3, "Write parser", "Handle \"quotes\".\n\nAnd paragraphs.");
+        task.depends_on = vec![1, 2];
+        task.due = NaiveDate::from_ymd_opt(2025, 3, 1);
+        task.checklist = vec![
+            ChecklistItem {
+                title: "Lexer".to_string(),
+                done: true,
+            },
+            ChecklistItem {
+                title: "Grammar".to_string(),
+                done: false,
+            },
+        ];
+        task
+    }
+
+    #[test]
+    fn markdown_puts_the_description_in_the_body() {
+        let text = to_text(&task(), EditFormat::Markdown).unwrap();
+        assert!(text.starts_with("+++\nid = 3\ntitle = \"Write parser\"\n"));
+        assert!(text.ends_with(
+            "+++\n\nHandle \"quotes\".\n\nAnd paragraphs.\n\n- [x] Lexer\n- [ ] Grammar\n"
+        ));
+    }
+
+    #[test]
+    fn tasks_survive_a_round_trip() {
+        let task = task();
+        for format in [EditFormat::Markdown, EditFormat::Toml] {
+            let text = to_text(&task, format).unwrap();
+            let mut parsed = from_text(&text, format).unwrap();
+            parsed.extract_checklist();
+            assert_eq!(parsed, task, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn front_matter_errors_point_into_the_edited_text() {
+        let text = "+++\nid = 3\ntitle = \"Plan\"\ndone = maybe\n+++\n\nFirst line\nsecond line\n";
+        let error = from_text(text, EditFormat::Markdown).unwrap_err();
+        assert_eq!(error.location, Some((4, 8)));
+
+        let text = "+++\nid = 3\n+++\n\nDescription\n";
+        let error = from_text(text, EditFormat::Markdown).unwrap_err();
+        assert!(error.message.contains("title"), "{}", error.message);
+    }
+
+    #[test]
+    fn front_matter_must_be_delimited() {
+        let error = from_text("id = 3\n", EditFormat::Markdown).unwrap_err();
+        assert_eq!(error.location, Some((1, 1)));
+        let error = from_text("+++\nid = 3\n", EditFormat::Markdown).unwrap_err();
+        assert_eq!(error.location, None);
+    }
+}
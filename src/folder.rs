@@ -0,0 +1,58 @@
+//! Sync through a shared folder, e.g. one kept in sync by Syncthing or Dropbox.
+
+use crate::persistence;
+use crate::sync::{RemoteFile, RemoteStore, Result};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Keeps the tasks file of each goal in a subdirectory of a shared folder.
+pub struct FolderStore {
+    root: Option<PathBuf>,
+}
+
+impl FolderStore {
+    /// Creates a store in `root`, which is unconfigured if `None`.
+    pub fn new(root: Option<PathBuf>) -> FolderStore {
+        FolderStore { root }
+    }
+
+    fn path(&self, goal: &str) -> Option<PathBuf> {
+        self.root
+            .as_ref()
+            .map(|root| root.join(goal).join(persistence::TASKS_FILE))
+    }
+}
+
+impl RemoteStore for FolderStore {
+    fn location(&self, goal: &str) -> Option<String> {
+        self.path(goal).map(|path| path.display().to_string())
+    }
+
+    fn read(&self, goal: &str) -> Result<Option<RemoteFile>> {
+        let Some(path) = self.path(goal) else {
+            return Ok(None);
+        };
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Some(RemoteFile {
+                content,
+                version: None,
+            })),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes through a temporary file, so that the folder never holds a
+    /// partially written tasks file for other devices to pick up.
+    fn write(&self, goal: &str, content: &str, _version: Option<&str>) -> Result<()> {
+        let Some(path) = self.path(goal) else {
+            return Ok(());
+        };
+        let temp = path.with_file_name(format!(".{}.tmp", persistence::TASKS_FILE));
+        fs::create_dir_all(path.parent().expect("tasks file has a parent directory"))?;
+        fs::write(&temp, content)?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+}
@@ -1,92 +1,925 @@
-//! Git integration.
-
-use std::io;
-use std::process::Command;
-use std::path::PathBuf;
-
-/// Returns the path to the tasks directory.
-fn get_tasks_dir() -> PathBuf {
-    dirs::home_dir()
-        .expect("Could not find home directory")
-        .join(".tasks")
-        .join("tasks")
-}
-
-/// Initializes a git repository in the tasks directory if it doesn't exist.
-pub fn init() -> io::Result<()> {
-    let dir = get_tasks_dir();
-    let git_dir = dir.join(".git");
-    if !git_dir.exists() {
-        Command::new("git")
-            .arg("-C")
-            .arg(&dir)
-            .arg("init")
-            .status()?;
+//! Git integration, using libgit2 instead of the `git` executable.
+
+use crate::config::GitConfig;
+use crate::crypto;
+use crate::merge;
+use crate::persistence;
+use crate::sync::SyncBackend;
+use crate::tasks::{Task, TaskList};
+use chrono::{DateTime, Local};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{
+    Commit as GitCommit, Cred, CredentialType, Direction, ErrorCode, FetchOptions, Oid,
+    PushOptions, RemoteCallbacks, Repository, Signature, StatusOptions,
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub use crate::sync::{Error, Result};
+
+/// Syncs each goal with a remote git repository.
+pub struct GitBackend {
+    config: GitConfig,
+}
+
+impl GitBackend {
+    /// Creates a backend using the remote and branch settings in `config`.
+    pub fn new(config: GitConfig) -> GitBackend {
+        GitBackend { config }
+    }
+}
+
+impl SyncBackend for GitBackend {
+    fn has_remote(&self, dir: &Path) -> Result<bool> {
+        Ok(dir.join(".git").exists() && has_remote(dir, &self.config)?)
+    }
+
+    fn remote_prompt(&self) -> Option<&'static str> {
+        Some("Remote repository URL")
+    }
+
+    fn add_remote(&self, dir: &Path, url: &str) -> Result<()> {
+        init(dir)?;
+        add_remote(dir, &self.config, url)
+    }
+
+    fn target(&self, dir: &Path) -> Result<String> {
+        Ok(target(dir, &self.config)?.to_string())
+    }
+
+    fn status(&self, dir: &Path) -> Result<Option<RepoStatus>> {
+        status(dir, &self.config).map(Some)
+    }
+
+    fn push(&self, dir: &Path, progress: &dyn Fn(&'static str)) -> Result<()> {
+        init(dir)?;
+        push(dir, &self.config, progress)
+    }
+
+    fn pull(&self, dir: &Path, progress: &dyn Fn(&'static str)) -> Result<()> {
+        init(dir)?;
+        pull(dir, &self.config, progress)
+    }
+}
+
+/// Files of a goal that are committed.
+const TRACKED_FILES: [&str; 2] = [persistence::TASKS_FILE, "tasks_undone.toml"];
+
+/// Initializes a git repository in the goal directory if it doesn't exist.
+pub fn init(dir: &Path) -> Result<()> {
+    if !dir.join(".git").exists() {
+        fs::create_dir_all(dir)?;
+        Repository::init(dir).context("git init")?;
+    }
+    Ok(())
+}
+
+/// The remote and branch a goal is synced with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// Name of the remote.
+    pub remote: String,
+    /// Name of the branch on the remote.
+    pub branch: String,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.remote, self.branch)
+    }
+}
+
+/// Resolves the remote and branch to sync with.
+///
+/// Settings missing from `git` are taken from the current branch: its name
+/// and its upstream remote, falling back to `origin`.
+pub fn target(dir: &Path, git: &GitConfig) -> Result<Target> {
+    repo_target(&open(dir)?, git)
+}
+
+fn repo_target(repo: &Repository, git: &GitConfig) -> Result<Target> {
+    let current = current_branch(repo)?;
+    let remote = match (&git.remote, &current) {
+        (Some(remote), _) => Some(remote.clone()),
+        (None, Some(branch)) => optional(
+            repo.config()?
+                .get_string(&format!("branch.{}.remote", branch)),
+        )?,
+        (None, None) => None,
+    };
+    Ok(Target {
+        remote: remote.unwrap_or_else(|| "origin".to_string()),
+        branch: git
+            .branch
+            .clone()
+            .or(current)
+            .unwrap_or_else(|| "main".to_string()),
+    })
+}
+
+/// Returns the name of the branch HEAD points to, even if it has no commits yet.
+fn current_branch(repo: &Repository) -> Result<Option<String>> {
+    let head = repo.find_reference("HEAD")?;
+    Ok(head
+        .symbolic_target()?
+        .and_then(|target| target.strip_prefix("refs/heads/"))
+        .map(str::to_string))
+}
+
+/// Clones the repository at `url` into `dir`.
+pub fn clone(url: &str, dir: &Path) -> Result<()> {
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
     }
+    let config = git2::Config::open_default()?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(&config));
+    RepoBuilder::new()
+        .fetch_options(options)
+        .clone(url, dir)
+        .context(&format!("git clone {}", url))?;
     Ok(())
 }
 
-/// Adds a remote to the git repository.
-pub fn add_remote(url: &str) -> io::Result<()> {
-    let dir = get_tasks_dir();
-    Command::new("git")
-        .arg("-C")
-        .arg(&dir)
-        .arg("remote")
-        .arg("add")
-        .arg("origin")
-        .arg(url)
-        .status()?;
+/// Adds the configured remote to the git repository.
+pub fn add_remote(dir: &Path, git: &GitConfig, url: &str) -> Result<()> {
+    let repo = open(dir)?;
+    let target = repo_target(&repo, git)?;
+    repo.remote(&target.remote, url)
+        .context(&format!("git remote add {} {}", target.remote, url))?;
     Ok(())
 }
 
-/// Checks if a remote is configured.
-pub fn has_remote() -> io::Result<bool> {
-    let dir = get_tasks_dir();
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(&dir)
-        .arg("remote")
-        .output()?;
-    Ok(!output.stdout.is_empty())
-}
-
-/// Pushes changes to the remote repository.
-pub fn push() -> io::Result<()> {
-    let dir = get_tasks_dir();
-    Command::new("git")
-        .arg("-C")
-        .arg(&dir)
-        .arg("add")
-        .arg("tasks.toml")
-        .arg("tasks_undone.toml")
-        .status()?;
-    Command::new("git")
-        .arg("-C")
-        .arg(&dir)
-        .arg("commit")
-        .arg("-m")
-        .arg("Update tasks")
-        .status()?;
-    Command::new("git")
-        .arg("-C")
-        .arg(&dir)
-        .arg("push")
-        .arg("origin")
-        .arg("main")
-        .status()?;
+/// Checks if the configured remote exists.
+pub fn has_remote(dir: &Path, git: &GitConfig) -> Result<bool> {
+    let repo = open(dir)?;
+    let target = repo_target(&repo, git)?;
+    Ok(repo
+        .remotes()?
+        .iter()
+        .any(|remote| matches!(remote, Ok(Some(remote)) if remote == target.remote)))
+}
+
+/// How the local repository compares to the configured remote branch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepoStatus {
+    /// Number of local commits not pushed yet.
+    pub ahead: usize,
+    /// Number of fetched remote commits not merged yet.
+    pub behind: usize,
+    /// If true, the task files have uncommitted changes.
+    pub dirty: bool,
+}
+
+/// Compares the repository with the last fetched state of the configured remote branch.
+pub fn status(dir: &Path, git: &GitConfig) -> Result<RepoStatus> {
+    let repo = open(dir)?;
+    let mut options = StatusOptions::new();
+    options.include_untracked(true);
+    for file in TRACKED_FILES {
+        options.pathspec(file);
+    }
+    let mut status = RepoStatus {
+        dirty: !repo.statuses(Some(&mut options))?.is_empty(),
+        ..RepoStatus::default()
+    };
+    let Some(head) = head_commit(&repo)? else {
+        return Ok(status);
+    };
+    let target = repo_target(&repo, git)?;
+    let upstream = format!("refs/remotes/{}/{}", target.remote, target.branch);
+    match optional(repo.refname_to_id(&upstream))? {
+        Some(upstream) => {
+            (status.ahead, status.behind) = repo.graph_ahead_behind(head.id(), upstream)?;
+        }
+        None => {
+            let mut walk = repo.revwalk()?;
+            walk.push(head.id())?;
+            status.ahead = walk.count();
+        }
+    }
+    Ok(status)
+}
+
+/// A commit that changed the tasks file.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    /// Abbreviated commit hash.
+    pub id: String,
+    /// When the commit was authored.
+    pub date: DateTime<Local>,
+    /// First line of the commit message.
+    pub subject: String,
+}
+
+/// A version of the tasks file, as committed.
+pub struct Version {
+    /// The commit that created this version.
+    pub commit: Commit,
+    /// The task list, or `None` if it could not be parsed.
+    pub tasks: Option<TaskList>,
+    /// The task list this version replaced, or `None` if there was none or it
+    /// could not be parsed.
+    pub previous: Option<TaskList>,
+}
+
+/// Lists up to `limit` commits that changed the tasks file with the versions
+/// they wrote, newest first.
+///
+/// Only first parents are followed, so each commit's predecessor in the list
+/// holds the version it changed. Each version is parsed only once.
+pub fn log(dir: &Path, limit: usize) -> Result<Vec<Version>> {
+    let repo = open(dir)?;
+    if head_commit(&repo)?.is_none() {
+        return Ok(Vec::new());
+    }
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.simplify_first_parent()?;
+    let mut parsed: HashMap<Oid, Option<TaskList>> = HashMap::new();
+    let mut parse = |id: Option<Oid>| -> Result<Option<TaskList>> {
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        if let Some(tasks) = parsed.get(&id) {
+            return Ok(tasks.clone());
+        }
+        let blob = repo.find_blob(id)?;
+        let tasks = persistence::parse_tasks(&String::from_utf8_lossy(blob.content())).ok();
+        parsed.insert(id, tasks.clone());
+        Ok(tasks)
+    };
+    let mut versions = Vec::new();
+    for id in walk {
+        if versions.len() >= limit {
+            break;
+        }
+        let commit = repo.find_commit(id?)?;
+        let previous = match commit.parent(0) {
+            Ok(parent) => tasks_file_id(&parent)?,
+            Err(_) => None,
+        };
+        let current = tasks_file_id(&commit)?;
+        if current == previous {
+            continue;
+        }
+        let time = commit.author().when();
+        versions.push(Version {
+            commit: Commit {
+                id: commit.as_object().short_id()?.as_str()?.to_string(),
+                date: DateTime::from_timestamp(time.seconds(), 0)
+                    .unwrap_or_default()
+                    .with_timezone(&Local),
+                subject: commit.summary()?.unwrap_or_default().to_string(),
+            },
+            tasks: parse(current)?,
+            previous: parse(previous)?,
+        });
+    }
+    Ok(versions)
+}
+
+/// Returns the task list as of `revision`, or `None` if it had no tasks file.
+pub fn tasks_at(dir: &Path, revision: &str) -> Result<Option<TaskList>> {
+    let repo = open(dir)?;
+    let Some(object) = optional(repo.revparse_single(revision))? else {
+        return Ok(None);
+    };
+    let commit = object.peel_to_commit()?;
+    tasks_in(&repo, &commit)
+}
+
+/// Returns the task list in `commit`, or `None` if it had no tasks file.
+fn tasks_in(repo: &Repository, commit: &GitCommit) -> Result<Option<TaskList>> {
+    let Some(id) = tasks_file_id(commit)? else {
+        return Ok(None);
+    };
+    let blob = repo.find_blob(id)?;
+    let content = String::from_utf8_lossy(blob.content());
+    Ok(Some(persistence::parse_tasks(&content)?))
+}
+
+/// Returns the id of the tasks file in `commit`, or `None` if it has none.
+fn tasks_file_id(commit: &GitCommit) -> Result<Option<Oid>> {
+    let entry = optional(commit.tree()?.get_path(Path::new(persistence::TASKS_FILE)))?;
+    Ok(entry.map(|entry| entry.id()))
+}
+
+/// Commits changed task files and pushes them to the configured remote and branch.
+///
+/// `progress` is called with the name of each step as it starts.
+pub fn push(dir: &Path, git: &GitConfig, progress: &dyn Fn(&'static str)) -> Result<()> {
+    progress("Committing");
+    commit(dir, None)?;
+    progress("Pushing");
+    let repo = open(dir)?;
+    let target = repo_target(&repo, git)?;
+    let head = repo.head()?;
+    let source = head.name()?;
+    let refspec = format!("{}:refs/heads/{}", source, target.branch);
+    let operation = format!("git push {} {}", target.remote, refspec);
+
+    let config = repo.config()?;
+    let rejection = RefCell::new(None);
+    let mut callbacks = callbacks(&config);
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            *rejection.borrow_mut() = Some(format!("{} was rejected: {}", reference, status));
+        }
+        Ok(())
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    let mut remote = repo.find_remote(&target.remote).context(&operation)?;
+    remote
+        .push(&[refspec.as_str()], Some(&mut options))
+        .context(&operation)?;
+    if let Some(message) = rejection.borrow_mut().take() {
+        return Err(Error::Git {
+            operation,
+            error: git2::Error::from_str(&message),
+        });
+    }
+    Ok(())
+}
+
+/// Fetches the configured remote branch and merges its tasks into the local ones.
+///
+/// Instead of a textual merge of `tasks.toml`, which would leave conflict
+/// markers behind, the task lists are merged by task id with
+/// [`merge::merge`] and the result is committed as a merge commit. The goal is
+/// locked while the tasks file is merged and written.
+/// `progress` is called with the name of each step as it starts.
+pub fn pull(dir: &Path, git: &GitConfig, progress: &dyn Fn(&'static str)) -> Result<()> {
+    progress("Fetching");
+    let repo = open(dir)?;
+    let unborn = head_commit(&repo)?.is_none();
+    if unborn && git.branch.is_none() {
+        adopt_remote_branch(&repo, git)?;
+    }
+    let target = repo_target(&repo, git)?;
+    let tracking = format!("refs/remotes/{}/{}", target.remote, target.branch);
+    fetch(&repo, &target, &tracking)?;
+    let Some(theirs_id) = optional(repo.refname_to_id(&tracking))? else {
+        return Ok(());
+    };
+    let theirs_commit = repo.find_commit(theirs_id)?;
+    let Some(theirs) = tasks_in(&repo, &theirs_commit)? else {
+        return Ok(());
+    };
+    progress("Merging");
+    let _lock = persistence::lock_goal(dir)?;
+    let path = dir.join(persistence::TASKS_FILE);
+    let ours = if path.exists() {
+        Some(persistence::parse_tasks(&fs::read_to_string(&path)?)?)
+    } else {
+        None
+    };
+
+    if unborn {
+        // Nothing committed yet: adopt the remote history and keep local tasks on top.
+        let branch = repo.find_reference("HEAD")?;
+        let branch = branch.symbolic_target()?.unwrap_or("refs/heads/main");
+        repo.reference(branch, theirs_id, true, "pull: adopt remote history")?;
+        let mut index = repo.index()?;
+        index.read_tree(&theirs_commit.tree()?)?;
+        index.write()?;
+        let merged = match ours {
+            Some(ours) => merge::merge(None, &ours, &theirs),
+            None => theirs,
+        };
+        persistence::persist_tasks(dir, &merged)?;
+        return commit(dir, None);
+    }
+
+    commit(dir, None)?;
+    let head = repo.head()?.peel_to_commit()?;
+    if head.id() == theirs_id || repo.graph_descendant_of(head.id(), theirs_id)? {
+        return Ok(());
+    }
+    if repo.graph_descendant_of(theirs_id, head.id())? {
+        repo.checkout_tree(
+            theirs_commit.as_object(),
+            Some(CheckoutBuilder::new().force()),
+        )?;
+        repo.head()?
+            .set_target(theirs_id, &format!("pull: fast-forward to {}", target))?;
+        return Ok(());
+    }
+
+    let base = match optional(repo.merge_base(head.id(), theirs_id))? {
+        Some(base) => tasks_in(&repo, &repo.find_commit(base)?)?,
+        None => None,
+    };
+    let merged = match ours {
+        Some(ours) => merge::merge(base.as_ref(), &ours, &theirs),
+        None => theirs,
+    };
+    // Like `git merge --strategy=ours`, keep our files and only bring in the merged tasks.
+    persistence::persist_tasks(dir, &merged)?;
+    let mut index = repo.index()?;
+    index.add_path(Path::new(persistence::TASKS_FILE))?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    repo.commit(
+        Some("HEAD"),
+        &signature(&repo)?,
+        &signature(&repo)?,
+        &format!("Merge tasks from {}", target),
+        &tree,
+        &[&head, &theirs_commit],
+    )
+    .context("git commit")?;
+    Ok(())
+}
+
+/// Fetches the target branch into the remote-tracking reference `tracking`.
+fn fetch(repo: &Repository, target: &Target, tracking: &str) -> Result<()> {
+    let operation = format!("git fetch {} {}", target.remote, target.branch);
+    let config = repo.config()?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(&config));
+    let refspec = format!("+refs/heads/{}:{}", target.branch, tracking);
+    repo.find_remote(&target.remote)
+        .context(&operation)?
+        .fetch(&[refspec.as_str()], Some(&mut options), None)
+        .context(&operation)
+}
+
+/// Names the unborn current branch after the default branch of the remote, so
+/// that a fresh goal directory follows e.g. `master` instead of assuming `main`.
+fn adopt_remote_branch(repo: &Repository, git: &GitConfig) -> Result<()> {
+    let target = repo_target(repo, git)?;
+    let operation = format!("git ls-remote {}", target.remote);
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(&target.remote).context(&operation)?;
+    let connection = remote
+        .connect_auth(Direction::Fetch, Some(callbacks(&config)), None)
+        .context(&operation)?;
+    let default_branch = connection
+        .default_branch()
+        .ok()
+        .and_then(|branch| branch.as_str().map(str::to_string).ok());
+    drop(connection);
+    if let Some(branch) = default_branch {
+        repo.set_head(&branch)?;
+    }
     Ok(())
 }
 
-/// Pulls changes from the remote repository.
-pub fn pull() -> io::Result<()> {
-    let dir = get_tasks_dir();
-    Command::new("git")
-        .arg("-C")
-        .arg(&dir)
-        .arg("pull")
-        .arg("origin")
-        .arg("main")
-        .status()?;
+/// Returns callbacks authenticating with ssh-agent, the default SSH keys, git
+/// credential helpers and finally the platform's default credentials.
+///
+/// Each method is tried once, so that authentication fails instead of looping
+/// or prompting on a terminal the TUI owns.
+fn callbacks(config: &git2::Config) -> RemoteCallbacks<'_> {
+    let mut tried = CredentialType::empty();
+    let mut tried_agent = env::var_os("SSH_AUTH_SOCK").is_none();
+    let mut keys = default_ssh_keys().into_iter();
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        let user = username.unwrap_or("git");
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(user);
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if !tried_agent {
+                tried_agent = true;
+                return Cred::ssh_key_from_agent(user);
+            }
+            if let Some(key) = keys.next() {
+                return Cred::ssh_key(user, None, &key, None);
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && !tried.contains(CredentialType::USER_PASS_PLAINTEXT)
+        {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            return Cred::credential_helper(config, url, username);
+        }
+        if allowed.contains(CredentialType::DEFAULT) && !tried.contains(CredentialType::DEFAULT) {
+            tried |= CredentialType::DEFAULT;
+            return Cred::default();
+        }
+        Err(git2::Error::from_str(
+            "Authentication failed; add your SSH key to ssh-agent or set up a git credential helper",
+        ))
+    });
+    callbacks
+}
+
+/// Returns the private keys in `~/.ssh` that ssh would try by default.
+fn default_ssh_keys() -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    ["id_ed25519", "id_ecdsa", "id_rsa"]
+        .iter()
+        .map(|name| home.join(".ssh").join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Opens the repository in `dir`, without looking in parent directories.
+fn open(dir: &Path) -> Result<Repository> {
+    Ok(Repository::open(dir)?)
+}
+
+/// Returns the commit HEAD points to, or `None` if nothing is committed yet.
+fn head_commit(repo: &Repository) -> Result<Option<GitCommit<'_>>> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit()?)),
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns the author of new commits, falling back to a generic one if git has
+/// no user configured.
+fn signature(repo: &Repository) -> Result<Signature<'static>> {
+    repo.signature()
+        .or_else(|_| Signature::now("Tasklings", "tasklings@localhost"))
+        .context("git commit")
+}
+
+/// Turns a "not found" error into `None`.
+fn optional<T>(result: std::result::Result<T, git2::Error>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Names the operation that failed in errors from libgit2.
+trait Context<T> {
+    fn context(self, operation: &str) -> Result<T>;
+}
+
+impl<T> Context<T> for std::result::Result<T, git2::Error> {
+    fn context(self, operation: &str) -> Result<T> {
+        self.map_err(|error| Error::Git {
+            operation: operation.to_string(),
+            error,
+        })
+    }
+}
+
+/// Commits the task files if they changed.
+///
+/// Without a `message`, one describing the change is generated.
+pub fn commit(dir: &Path, message: Option<&str>) -> Result<()> {
+    let repo = open(dir)?;
+    let mut index = repo.index()?;
+    for file in TRACKED_FILES {
+        if dir.join(file).exists() {
+            index.add_path(Path::new(file))?;
+        }
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let parent = head_commit(&repo)?;
+    let unchanged = match &parent {
+        Some(parent) => parent.tree_id() == tree.id(),
+        None => tree.is_empty(),
+    };
+    if unchanged {
+        return Ok(());
+    }
+    let message = match message {
+        Some(message) => message.to_string(),
+        None => generate_message(dir),
+    };
+    let signature = signature(&repo)?;
+    let parents: Vec<&GitCommit> = parent.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        &message,
+        &tree,
+        &parents,
+    )
+    .context("git commit")?;
     Ok(())
 }
+
+/// Describes the staged change of the tasks file.
+fn generate_message(dir: &Path) -> String {
+    let content = fs::read_to_string(dir.join(persistence::TASKS_FILE)).ok();
+    let before = || tasks_at(dir, "HEAD").ok().flatten();
+    commit_message(content.as_deref(), before, crypto::is_enabled())
+}
+
+/// Describes the change from the committed tasks, returned by `before`, to the
+/// tasks file `content`.
+///
+/// This is best effort: if either version can't be parsed, a generic message is used.
+/// Encrypted tasks, or all tasks if `encrypting`, also get the generic message, since
+/// commit messages are not encrypted and task titles would reach the remote in them.
+fn commit_message(
+    content: Option<&str>,
+    before: impl FnOnce() -> Option<TaskList>,
+    encrypting: bool,
+) -> String {
+    if encrypting || content.is_some_and(crypto::is_encrypted) {
+        return DEFAULT_MESSAGE.to_string();
+    }
+    match content.and_then(|content| persistence::parse_tasks(content).ok()) {
+        Some(after) => describe_changes(before().as_ref(), &after),
+        None => DEFAULT_MESSAGE.to_string(),
+    }
+}
+
+/// Commit message used when a change can't be described more precisely.
+const DEFAULT_MESSAGE: &str = "Update tasks";
+
+/// Describes the change from `before` to `after`, e.g. "Done #12: Write parser; Added 3 tasks".
+pub fn describe_changes(before: Option<&TaskList>, after: &TaskList) -> String {
+    let old: HashMap<i32, &Task> = before
+        .map(|list| list.tasks.iter().map(|t| (t.id, t)).collect())
+        .unwrap_or_default();
+    let new_ids: HashSet<i32> = after.tasks.iter().map(|t| t.id).collect();
+
+    let (mut done, mut reopened, mut added, mut edited) = (vec![], vec![], vec![], vec![]);
+    for task in &after.tasks {
+        match old.get(&task.id) {
+            None => added.push(task),
+            Some(old) if !old.done && task.done => done.push(task),
+            Some(old) if old.done && !task.done => reopened.push(task),
+            Some(old) if *old != task => edited.push(task),
+            Some(_) => {}
+        }
+    }
+    let removed: Vec<&Task> = before
+        .map(|list| {
+            list.tasks
+                .iter()
+                .filter(|t| !new_ids.contains(&t.id))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut parts: Vec<String> = [
+        ("Done", &done),
+        ("Added", &added),
+        ("Reopened", &reopened),
+        ("Edited", &edited),
+        ("Removed", &removed),
+    ]
+    .into_iter()
+    .filter_map(|(verb, tasks)| match tasks.as_slice() {
+        [] => None,
+        [task] => Some(format!("{} #{}: {}", verb, task.id, task.title)),
+        tasks => Some(format!("{} {} tasks", verb, tasks.len())),
+    })
+    .collect();
+    if let Some(before) = before {
+        if before.the_goal != after.the_goal || before.target_date != after.target_date {
+            parts.push("Updated goal".to_string());
+        }
+    }
+    if parts.is_empty() {
+        DEFAULT_MESSAGE.to_string()
+    } else {
+        parts.join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::testing::{edit_tasks, write_tasks};
+    use tempfile::TempDir;
+
+    #[test]
+    fn describes_single_changes() {
+        let before = TaskList::for_test(&["Write parser", "Ship"]);
+
+        let mut after = before.clone();
+        after.tasks[0].done = true;
+        assert_eq!(
+            describe_changes(Some(&before), &after),
+            "Done #1: Write parser"
+        );
+        assert_eq!(
+            describe_changes(Some(&after), &before),
+            "Reopened #1: Write parser"
+        );
+
+        let mut after = before.clone();
+        after.tasks.pop();
+        assert_eq!(describe_changes(Some(&before), &after), "Removed #2: Ship");
+
+        let mut after = before.clone();
+        after.tasks.push(Task::new(3, "Celebrate", ""));
+        assert_eq!(
+            describe_changes(Some(&before), &after),
+            "Added #3: Celebrate"
+        );
+
+        let mut after = before.clone();
+        after.tasks[1].title = "Ship it".to_string();
+        assert_eq!(
+            describe_changes(Some(&before), &after),
+            "Edited #2: Ship it"
+        );
+    }
+
+    #[test]
+    fn counts_several_changes_of_a_kind() {
+        let after = TaskList::for_test(&["One", "Two"]);
+        assert_eq!(describe_changes(None, &after), "Added 2 tasks");
+
+        let mut before = after.clone();
+        before.tasks.pop();
+        before.the_goal = "Old goal".to_string();
+        assert_eq!(
+            describe_changes(Some(&before), &after),
+            "Added #2: Two; Updated goal"
+        );
+
+        let mut done = after.clone();
+        done.tasks.iter_mut().for_each(|task| task.done = true);
+        assert_eq!(describe_changes(Some(&after), &done), "Done 2 tasks");
+    }
+
+    #[test]
+    fn falls_back_to_the_default_message() {
+        let before = TaskList::for_test(&["One"]);
+        assert_eq!(describe_changes(Some(&before), &before), DEFAULT_MESSAGE);
+    }
+
+    /// A bare repository standing in for the remote, and goal directories syncing with it.
+    struct Remote {
+        root: TempDir,
+        url: String,
+    }
+
+    impl Remote {
+        /// Creates a bare repository whose default branch is `branch`.
+        fn new(branch: &str) -> Remote {
+            let root = TempDir::new().unwrap();
+            let path = root.path().join("remote.git");
+            let repo = Repository::init_bare(&path).unwrap();
+            repo.set_head(&format!("refs/heads/{}", branch)).unwrap();
+            let url = path.to_str().unwrap().to_string();
+            Remote { root, url }
+        }
+
+        /// Initializes the goal `work` of `machine` on `branch` with the remote added.
+        fn init(&self, machine: &str, branch: &str) -> PathBuf {
+            let dir = self.root.path().join(machine).join("work");
+            init(&dir).unwrap();
+            open(&dir)
+                .unwrap()
+                .set_head(&format!("refs/heads/{}", branch))
+                .unwrap();
+            add_remote(&dir, &GitConfig::default(), &self.url).unwrap();
+            dir
+        }
+
+        /// Clones the remote as the goal `work` of `machine`.
+        fn clone(&self, machine: &str) -> PathBuf {
+            let dir = self.root.path().join(machine).join("work");
+            clone(&self.url, &dir).unwrap();
+            dir
+        }
+
+        fn head(&self, branch: &str) -> Oid {
+            Repository::open_bare(&self.url)
+                .unwrap()
+                .refname_to_id(&format!("refs/heads/{}", branch))
+                .unwrap()
+        }
+    }
+
+    fn head(dir: &Path) -> (Oid, usize, String) {
+        let repo = open(dir).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let message = head.summary().unwrap().unwrap().to_string();
+        (head.id(), head.parent_count(), message)
+    }
+
+    #[test]
+    fn pushes_and_fast_forwards() {
+        let remote = Remote::new("main");
+        let ours = remote.init("ours", "main");
+        write_tasks(&ours, &["Plan", "Build"]);
+        push(&ours, &GitConfig::default(), &|_| {}).unwrap();
+        assert_eq!(remote.head("main"), head(&ours).0);
+        assert_eq!(head(&ours).2, "Added 2 tasks");
+
+        let theirs = remote.clone("theirs");
+        edit_tasks(&ours, |tasks| tasks.tasks[0].done = true);
+        push(&ours, &GitConfig::default(), &|_| {}).unwrap();
+        pull(&theirs, &GitConfig::default(), &|_| {}).unwrap();
+
+        let (id, parents, message) = head(&theirs);
+        assert_eq!(id, remote.head("main"));
+        assert_eq!(parents, 1);
+        assert_eq!(message, "Done #1: Plan");
+        assert!(persistence::read_tasks(&theirs).unwrap().tasks[0].done);
+    }
+
+    #[test]
+    fn diverging_pull_creates_a_merge_commit() {
+        let remote = Remote::new("main");
+        let ours = remote.init("ours", "main");
+        write_tasks(&ours, &["Plan", "Build"]);
+        push(&ours, &GitConfig::default(), &|_| {}).unwrap();
+        let theirs = remote.clone("theirs");
+
+        edit_tasks(&ours, |tasks| tasks.tasks[0].title = "Plan it".to_string());
+        push(&ours, &GitConfig::default(), &|_| {}).unwrap();
+        edit_tasks(&theirs, |tasks| tasks.tasks[1].done = true);
+        commit(&theirs, None).unwrap();
+        let before = head(&theirs).0;
+        pull(&theirs, &GitConfig::default(), &|_| {}).unwrap();
+
+        let (_, parents, message) = head(&theirs);
+        assert_eq!(parents, 2);
+        assert_eq!(message, "Merge tasks from origin/main");
+        let repo = open(&theirs).unwrap();
+        let merge = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(merge.parent_id(0).unwrap(), before);
+        assert_eq!(merge.parent_id(1).unwrap(), remote.head("main"));
+        assert_eq!(
+            persistence::read_tasks(&theirs).unwrap().titles(),
+            ["Plan it", "Build"]
+        );
+        assert!(persistence::read_tasks(&theirs).unwrap().tasks[1].done);
+    }
+
+    #[test]
+    fn pull_into_an_unborn_branch_adopts_the_remote_branch() {
+        let remote = Remote::new("trunk");
+        let ours = remote.init("ours", "trunk");
+        write_tasks(&ours, &["Plan"]);
+        push(&ours, &GitConfig::default(), &|_| {}).unwrap();
+
+        let theirs = remote.init("theirs", "main");
+        write_tasks(&theirs, &["Local"]);
+        pull(&theirs, &GitConfig::default(), &|_| {}).unwrap();
+
+        let repo = open(&theirs).unwrap();
+        assert_eq!(current_branch(&repo).unwrap().as_deref(), Some("trunk"));
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.parent_id(0).unwrap(), remote.head("trunk"));
+        assert_eq!(
+            persistence::read_tasks(&theirs).unwrap().titles(),
+            ["Plan", "Local"]
+        );
+    }
+
+    #[test]
+    fn rejected_push_is_a_git_error() {
+        let remote = Remote::new("main");
+        let ours = remote.init("ours", "main");
+        write_tasks(&ours, &["Plan"]);
+        push(&ours, &GitConfig::default(), &|_| {}).unwrap();
+        let theirs = remote.clone("theirs");
+        edit_tasks(&ours, |tasks| tasks.tasks[0].done = true);
+        push(&ours, &GitConfig::default(), &|_| {}).unwrap();
+
+        edit_tasks(&theirs, |tasks| {
+            tasks.tasks[0].title = "Plan it".to_string()
+        });
+        let error = push(&theirs, &GitConfig::default(), &|_| {}).unwrap_err();
+
+        match error {
+            Error::Git { operation, .. } => {
+                assert_eq!(operation, "git push origin refs/heads/main:refs/heads/main")
+            }
+            e => panic!("expected a git error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn commits_of_encrypted_tasks_do_not_name_them() {
+        let after = TaskList::for_test(&["Secret plan"]);
+        let content = toml::to_string_pretty(&after).unwrap();
+        let content = Some(content.as_str());
+        let before = || Some(TaskList::for_test(&[]));
+
+        assert_eq!(
+            commit_message(content, before, false),
+            "Added #1: Secret plan"
+        );
+        assert_eq!(commit_message(content, before, true), DEFAULT_MESSAGE);
+        let encrypted =
+            "-----BEGIN AGE ENCRYPTED FILE-----\nYWdl\n-----END AGE ENCRYPTED FILE-----\n";
+        assert_eq!(
+            commit_message(Some(encrypted), before, false),
+            DEFAULT_MESSAGE
+        );
+        assert_eq!(
+            commit_message(Some("tasks = ["), before, false),
+            DEFAULT_MESSAGE
+        );
+    }
+}
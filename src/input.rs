@@ -1,10 +1,12 @@
 //! User input handling.
 
-use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::io;
 
 /// Input events.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum InputEvent {
     Quit,
     MarkDone,
@@ -18,32 +20,477 @@ pub enum InputEvent {
     EditTask,
     GitPush,
     GitPull,
+    OpenGoals,
+    ToggleStats,
+    ToggleHeatmap,
+    ToggleHistory,
+    ToggleHelp,
+    NextChecklistItem,
+    PreviousChecklistItem,
+    ToggleChecklistItem,
+    Restore,
+    Select,
     Noop,
 }
 
-/// Handles user input.
-pub fn handle_input() -> io::Result<InputEvent> {
+/// The default binding of an action.
+struct Binding {
+    event: InputEvent,
+    /// Name of the action in the configuration.
+    action: &'static str,
+    /// Short label shown in the footer.
+    hint: &'static str,
+    /// Description shown on the help screen.
+    description: &'static str,
+    keys: &'static [&'static str],
+}
+
+/// Default bindings, in the order they are displayed.
+const DEFAULT_BINDINGS: &[Binding] = &[
+    Binding {
+        event: InputEvent::MarkDone,
+        action: "mark_done",
+        hint: "mark done",
+        description: "Mark the task as done",
+        keys: &["d"],
+    },
+    Binding {
+        event: InputEvent::MarkUndone,
+        action: "mark_undone",
+        hint: "mark undone",
+        description: "Mark the task as not done",
+        keys: &["u"],
+    },
+    Binding {
+        event: InputEvent::PreviousTask,
+        action: "previous_task",
+        hint: "prev",
+        description: "Go to the previous task",
+        keys: &["k", "Left"],
+    },
+    Binding {
+        event: InputEvent::NextTask,
+        action: "next_task",
+        hint: "next",
+        description: "Go to the next task",
+        keys: &["j", "Right"],
+    },
+    Binding {
+        event: InputEvent::NextUndoneTask,
+        action: "next_undone_task",
+        hint: "next undone",
+        description: "Go to the next undone task",
+        keys: &["N"],
+    },
+    Binding {
+        event: InputEvent::FirstUndone,
+        action: "first_undone",
+        hint: "first undone",
+        description: "Go to the first undone task",
+        keys: &["f"],
+    },
+    Binding {
+        event: InputEvent::LastTask,
+        action: "last_task",
+        hint: "last",
+        description: "Go to the last task",
+        keys: &["l"],
+    },
+    Binding {
+        event: InputEvent::NextChecklistItem,
+        action: "next_checklist_item",
+        hint: "next item",
+        description: "Highlight the next checklist item",
+        keys: &["J", "Down"],
+    },
+    Binding {
+        event: InputEvent::PreviousChecklistItem,
+        action: "previous_checklist_item",
+        hint: "prev item",
+        description: "Highlight the previous checklist item",
+        keys: &["K", "Up"],
+    },
+    Binding {
+        event: InputEvent::ToggleChecklistItem,
+        action: "toggle_checklist_item",
+        hint: "toggle item",
+        description: "Toggle the highlighted checklist item",
+        keys: &["x", "Space"],
+    },
+    Binding {
+        event: InputEvent::NewTask,
+        action: "new_task",
+        hint: "new",
+        description: "Create a new task in the editor",
+        keys: &["n"],
+    },
+    Binding {
+        event: InputEvent::EditTask,
+        action: "edit_task",
+        hint: "edit",
+        description: "Edit the task in the editor",
+        keys: &["e"],
+    },
+    Binding {
+        event: InputEvent::GitPull,
+        action: "git_pull",
+        hint: "pull",
+        description: "Pull tasks from the remote",
+        keys: &["p"],
+    },
+    Binding {
+        event: InputEvent::GitPush,
+        action: "git_push",
+        hint: "push",
+        description: "Push tasks to the remote",
+        keys: &["P"],
+    },
+    Binding {
+        event: InputEvent::OpenGoals,
+        action: "open_goals",
+        hint: "goals",
+        description: "Open the goal picker",
+        keys: &["g"],
+    },
+    Binding {
+        event: InputEvent::ToggleStats,
+        action: "toggle_stats",
+        hint: "stats",
+        description: "Toggle the statistics screen",
+        keys: &["s"],
+    },
+    Binding {
+        event: InputEvent::ToggleHeatmap,
+        action: "toggle_heatmap",
+        hint: "heatmap",
+        description: "Toggle the completion heatmap",
+        keys: &["h"],
+    },
+    Binding {
+        event: InputEvent::ToggleHistory,
+        action: "toggle_history",
+        hint: "history",
+        description: "Toggle the history of the task list",
+        keys: &["H"],
+    },
+    Binding {
+        event: InputEvent::ToggleHelp,
+        action: "toggle_help",
+        hint: "help",
+        description: "Toggle this help screen",
+        keys: &["?"],
+    },
+    Binding {
+        event: InputEvent::Restore,
+        action: "restore",
+        hint: "restore",
+        description: "Restore the selected version from the history",
+        keys: &["R"],
+    },
+    Binding {
+        event: InputEvent::Select,
+        action: "select",
+        hint: "select",
+        description: "Confirm the selection in a picker or preview a version in the history",
+        keys: &["Enter"],
+    },
+    Binding {
+        event: InputEvent::Quit,
+        action: "quit",
+        hint: "quit",
+        description: "Close the picker or quit",
+        keys: &["q", "Esc"],
+    },
+];
+
+/// A key together with its modifiers, such as `Ctrl+s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// Creates a chord, ignoring Shift on characters since it is part of the character.
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        KeyChord { code, modifiers }
+    }
+
+    /// Parses a chord such as `q`, `Esc`, `F5` or `Ctrl+s`.
+    fn parse(chord: &str) -> Option<KeyChord> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut key = chord;
+        while let Some((modifier, rest)) = key.split_once('+').filter(|(_, rest)| !rest.is_empty())
+        {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+            key = rest;
+        }
+        Some(KeyChord::new(parse_key(key)?, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{}", code),
+        }
+    }
+}
+
+/// Maps key chords to input events.
+pub struct Keymap {
+    bindings: HashMap<KeyChord, InputEvent>,
+    /// Keys of each event, in display order.
+    keys: Vec<(InputEvent, Vec<KeyChord>)>,
+}
+
+impl Keymap {
+    /// Creates the keymap from the default bindings and the configured `overrides`.
+    ///
+    /// The keys configured for an action replace all of its default keys.
+    pub fn new(overrides: &BTreeMap<String, Vec<String>>) -> io::Result<Keymap> {
+        if let Some(action) = overrides
+            .keys()
+            .find(|action| !DEFAULT_BINDINGS.iter().any(|b| b.action == *action))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown action '{}' in keybindings", action),
+            ));
+        }
+        let mut bindings = HashMap::new();
+        let mut keys = Vec::new();
+        for binding in DEFAULT_BINDINGS {
+            let names: Vec<&str> = match overrides.get(binding.action) {
+                Some(names) => names.iter().map(String::as_str).collect(),
+                None => binding.keys.to_vec(),
+            };
+            let mut chords = Vec::new();
+            for name in names {
+                let chord = KeyChord::parse(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unknown key '{}' for action '{}'", name, binding.action),
+                    )
+                })?;
+                if let Some(other) = bindings.insert(chord, binding.event) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Key '{}' is bound to both '{}' and '{}'",
+                            name,
+                            action_name(other),
+                            binding.action
+                        ),
+                    ));
+                }
+                chords.push(chord);
+            }
+            keys.push((binding.event, chords));
+        }
+        Ok(Keymap { bindings, keys })
+    }
+
+    /// Returns the event bound to `code` with `modifiers`.
+    pub fn event(&self, code: KeyCode, modifiers: KeyModifiers) -> InputEvent {
+        self.bindings
+            .get(&KeyChord::new(code, modifiers))
+            .copied()
+            .unwrap_or(InputEvent::Noop)
+    }
+
+    /// Returns the keys bound to `event`, such as `j/→`.
+    pub fn keys(&self, event: InputEvent) -> String {
+        self.keys
+            .iter()
+            .find(|(e, _)| *e == event)
+            .map(|(_, chords)| {
+                chords
+                    .iter()
+                    .map(KeyChord::to_string)
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns footer hints such as `[j/→]:next / [q]:quit` for `entries`.
+    ///
+    /// Events without keys are left out.
+    pub fn hints(&self, entries: &[(InputEvent, &str)]) -> String {
+        entries
+            .iter()
+            .filter_map(|(event, label)| {
+                let keys = self.keys(*event);
+                (!keys.is_empty()).then(|| format!("[{}]:{}", keys, label))
+            })
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+
+    /// Returns footer hints for every action of the task view, starting with help
+    /// so that it stays visible on narrow terminals.
+    pub fn footer(&self) -> String {
+        let help = DEFAULT_BINDINGS
+            .iter()
+            .filter(|b| b.event == InputEvent::ToggleHelp);
+        let rest = DEFAULT_BINDINGS.iter().filter(|b| {
+            !matches!(
+                b.event,
+                InputEvent::ToggleHelp | InputEvent::Restore | InputEvent::Select
+            )
+        });
+        let entries: Vec<(InputEvent, &str)> =
+            help.chain(rest).map(|b| (b.event, b.hint)).collect();
+        self.hints(&entries)
+    }
+
+    /// Returns the keys and the description of every action, in display order.
+    pub fn help(&self) -> Vec<(String, &'static str)> {
+        DEFAULT_BINDINGS
+            .iter()
+            .map(|b| (self.keys(b.event), b.description))
+            .collect()
+    }
+}
+
+/// Returns the configuration name of the action triggered by `event`.
+fn action_name(event: InputEvent) -> &'static str {
+    DEFAULT_BINDINGS
+        .iter()
+        .find(|b| b.event == event)
+        .map(|b| b.action)
+        .unwrap_or("noop")
+}
+
+/// Parses a key name such as `q`, `Esc` or `F5`.
+fn parse_key(key: &str) -> Option<KeyCode> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    let code = match key.to_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        other => KeyCode::F(other.strip_prefix('f')?.parse().ok()?),
+    };
+    Some(code)
+}
+
+/// Waits briefly for a key press and returns it, if any.
+pub fn read_key() -> io::Result<Option<KeyEvent>> {
     if event::poll(std::time::Duration::from_millis(250))? {
-        let event = event::read()?;
-        if let Event::Key(key) = event {
+        if let Event::Key(key) = event::read()? {
             if key.kind == KeyEventKind::Press {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Esc => return Ok(InputEvent::Quit),
-                    KeyCode::Char('d') => return Ok(InputEvent::MarkDone),
-                    KeyCode::Char('u') => return Ok(InputEvent::MarkUndone),
-                    KeyCode::Char('j') | KeyCode::Right => return Ok(InputEvent::NextTask),
-                    KeyCode::Char('k') | KeyCode::Left => return Ok(InputEvent::PreviousTask),
-                    KeyCode::Char('N') => return Ok(InputEvent::NextUndoneTask),
-                    KeyCode::Char('f') => return Ok(InputEvent::FirstUndone),
-                    KeyCode::Char('l') => return Ok(InputEvent::LastTask),
-                    KeyCode::Char('n') => return Ok(InputEvent::NewTask),
-                    KeyCode::Char('e') => return Ok(InputEvent::EditTask),
-                    KeyCode::Char('P') => return Ok(InputEvent::GitPush),
-                    KeyCode::Char('p') => return Ok(InputEvent::GitPull),
-                    _ => {}
-                }
+                return Ok(Some(key));
             }
         }
     }
-    Ok(InputEvent::Noop)
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(action: &str, keys: &[&str]) -> io::Result<Keymap> {
+        let keys = keys.iter().map(|key| key.to_string()).collect();
+        Keymap::new(&BTreeMap::from([(action.to_string(), keys)]))
+    }
+
+    #[test]
+    fn parses_chords() {
+        let chord = |code, modifiers| Some(KeyChord::new(code, modifiers));
+        assert_eq!(
+            KeyChord::parse("q"),
+            chord(KeyCode::Char('q'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            KeyChord::parse("ctrl+S"),
+            chord(KeyCode::Char('S'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            KeyChord::parse("Alt+Shift+F5"),
+            chord(KeyCode::F(5), KeyModifiers::ALT | KeyModifiers::SHIFT)
+        );
+        assert_eq!(
+            KeyChord::parse("Ctrl++"),
+            chord(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_chords() {
+        for chord in ["", "Ctrl+", "Hyper+x", "Ctrl+Nope", "Fx", "Esc+q"] {
+            assert_eq!(KeyChord::parse(chord), None, "{:?}", chord);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_keybindings() {
+        let error = keymap("mark_done", &["Ctrl+Nope"]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Unknown key 'Ctrl+Nope' for action 'mark_done'"
+        );
+        let error = keymap("fly", &["f"]).err().unwrap();
+        assert_eq!(error.to_string(), "Unknown action 'fly' in keybindings");
+        let error = keymap("mark_done", &["u"]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Key 'u' is bound to both 'mark_done' and 'mark_undone'"
+        );
+    }
+
+    #[test]
+    fn configured_keys_replace_the_defaults() {
+        let keymap = keymap("mark_done", &["Ctrl+d"]).unwrap();
+        assert_eq!(
+            keymap.event(KeyCode::Char('d'), KeyModifiers::CONTROL),
+            InputEvent::MarkDone
+        );
+        assert_eq!(
+            keymap.event(KeyCode::Char('d'), KeyModifiers::NONE),
+            InputEvent::Noop
+        );
+    }
 }
@@ -1,52 +1,344 @@
 //! The main entry point for the Tasklings application.
 
+use chrono::{Datelike, Local, NaiveDate};
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{DisableMouseCapture, EnableMouseCapture, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    symbols,
+    text::{Line, Span, Text},
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Chart, Clear, Dataset, GraphType, List,
+        ListItem, ListState, Paragraph, Sparkline, Wrap,
+    },
     Frame, Terminal,
 };
-use std::{error::Error, io};
+use std::{
+    error::Error,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::Path,
+    process::{Command, ExitCode},
+};
 
 mod app;
+mod backup;
+mod cli;
+mod config;
+mod crypto;
+mod editing;
+mod folder;
 mod git;
 mod input;
+mod merge;
+mod onboarding;
 mod persistence;
+mod stats;
+mod sync;
 mod tasks;
+mod watcher;
+mod webdav;
 
-use app::App;
+use app::{App, GoalPicker, Popup, Prompt, View};
+use backup::Backup;
+use clap::Parser;
+use cli::Cli;
+use config::{Config, Theme};
 use input::InputEvent;
+use onboarding::{Onboarding, Setup, Step};
+use persistence::ParseError;
+use stats::Stats;
+
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = match Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: failed to load configuration: {}", e);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if !matches!(cli.command, Some(cli::Command::Keygen { .. })) {
+        if let Err(e) = crypto::unlock(&config.encryption, read_passphrase) {
+            eprintln!("error: {}", e);
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    if let Some(command) = cli.command {
+        return Ok(cli::run(command, &config, cli.goal, cli.json));
+    }
+
+    let mut goal = cli.goal;
+    if onboarding::is_first_run(&config.data_dir)? {
+        let mut terminal = setup_terminal()?;
+        let res = run_onboarding(&mut terminal, &config, goal.as_deref());
+        restore_terminal(&mut terminal)?;
+        match res? {
+            Some(setup_goal) => goal = Some(setup_goal),
+            None => return Ok(ExitCode::SUCCESS),
+        }
+    }
+
+    let mut restored = None;
+    let mut app = loop {
+        let error = match App::new(config.clone(), goal.clone()) {
+            Ok(app) => break app,
+            Err(e) => e,
+        };
+        let Some(parse_error) = ParseError::find(&error) else {
+            eprintln!("error: {}", error);
+            return Ok(ExitCode::FAILURE);
+        };
+        let goal = goal.get_or_insert_with(|| persistence::load_active_goal(&config.data_dir));
+        let mut terminal = setup_terminal()?;
+        let res = run_recovery(&mut terminal, &config, goal, parse_error);
+        restore_terminal(&mut terminal)?;
+        match res? {
+            Recovery::Edited => {}
+            Recovery::Restored(backup) => restored = Some(backup),
+            Recovery::Quit => {
+                let dir = persistence::get_goal_dir(&config.data_dir, goal);
+                let path = persistence::get_tasks_file(&dir);
+                eprintln!("error: {}: {}", path.display(), parse_error);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    };
+    if let Some(backup) = restored {
+        app.message = Some(format!(
+            "Restored the backup from {}; the broken file is kept as {}",
+            backup.taken_at.format("%Y-%m-%d %H:%M"),
+            backup::BROKEN_FILE
+        ));
+    }
+
+    let mut terminal = setup_terminal()?;
+    let res = run_app(&mut terminal, &mut app);
+    restore_terminal(&mut terminal)?;
+
+    if res.is_ok() {
+        app.wait_for_sync();
+        if let Some(popup) = app.popup.take() {
+            eprintln!("error: {}: {}", popup.title, popup.body);
+        }
+        if let Err(e) = app.push_on_quit() {
+            eprintln!("error: push failed: {}", e);
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    if let Err(err) = res {
+        println!("{:?}", err);
+        return Ok(ExitCode::FAILURE);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // setup terminal
+/// Enters raw mode and the alternate screen.
+fn setup_terminal() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    let mut app = App::new()?;
-    let res = run_app(&mut terminal, &mut app);
+    Terminal::new(backend)
+}
 
+/// Leaves raw mode and the alternate screen.
+fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
         DisableMouseCapture
     )?;
-    terminal.show_cursor()?;
+    terminal.show_cursor()
+}
 
-    if let Err(err) = res {
-        println!("{:?}", err)
+/// Asks for the passphrase of the encryption identity on the terminal, without echoing it.
+fn read_passphrase() -> io::Result<String> {
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "Set {} to unlock the encryption identity",
+                crypto::PASSPHRASE_ENV
+            ),
+        ));
+    }
+    eprint!("Passphrase: ");
+    io::stderr().flush()?;
+    enable_raw_mode()?;
+    let mut passphrase = String::new();
+    let result = loop {
+        let key = match input::read_key() {
+            Ok(Some(key)) => key,
+            Ok(None) => continue,
+            Err(e) => break Err(e),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                break Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "Unlock cancelled",
+                ))
+            }
+            KeyCode::Esc => {
+                break Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "Unlock cancelled",
+                ))
+            }
+            KeyCode::Backspace => {
+                passphrase.pop();
+            }
+            KeyCode::Char(c) => passphrase.push(c),
+            _ => {}
+        }
+    };
+    disable_raw_mode()?;
+    eprintln!();
+    result.map(|()| passphrase)
+}
+
+/// Runs the first-run wizard and returns the goal it set up, or `None` if the user quit.
+///
+/// A cloned repository or the demo tasks become `goal`, or the default goal if `None`.
+fn run_onboarding<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    config: &Config,
+    goal: Option<&str>,
+) -> io::Result<Option<String>> {
+    let mut wizard = Onboarding::default();
+    loop {
+        terminal.draw(|f| render_onboarding(f, &wizard, &config.theme))?;
+        let Some(key) = input::read_key()? else {
+            continue;
+        };
+        let setup = match (wizard.step, key.code) {
+            (_, KeyCode::Esc) | (Step::Choose, KeyCode::Char('q')) => {
+                if wizard.back() {
+                    continue;
+                }
+                return Ok(None);
+            }
+            (_, KeyCode::Enter) => wizard.submit(),
+            (Step::Choose, KeyCode::Down | KeyCode::Char('j')) => {
+                wizard.next();
+                None
+            }
+            (Step::Choose, KeyCode::Up | KeyCode::Char('k')) => {
+                wizard.previous();
+                None
+            }
+            (_, KeyCode::Backspace) => {
+                wizard.input.pop();
+                None
+            }
+            (Step::CloneUrl | Step::GoalTitle, KeyCode::Char(c)) => {
+                wizard.input.push(c);
+                None
+            }
+            _ => None,
+        };
+        let Some(setup) = setup else {
+            continue;
+        };
+        if let Setup::Clone(url) = &setup {
+            wizard.message = Some(format!("Cloning {}...", url));
+            terminal.draw(|f| render_onboarding(f, &wizard, &config.theme))?;
+        }
+        match onboarding::apply(&config.data_dir, &setup, goal) {
+            Ok(goal) => return Ok(Some(goal)),
+            Err(e) => wizard.message = Some(e.to_string()),
+        }
     }
+}
+
+/// How the user left the recovery screen.
+enum Recovery {
+    /// The tasks file was opened in the editor.
+    Edited,
+    /// The tasks file was replaced with a backup.
+    Restored(Backup),
+    /// The user quit.
+    Quit,
+}
+
+/// Shows why the tasks file of `goal` can't be read and lets the user edit it,
+/// restore the newest readable backup or quit.
+fn run_recovery<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    config: &Config,
+    goal: &str,
+    error: &ParseError,
+) -> io::Result<Recovery> {
+    let path = persistence::get_tasks_file(&persistence::get_goal_dir(&config.data_dir, goal));
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    let mut lines = vec![
+        Line::from(format!("The tasks of '{}' can't be read:", goal)),
+        Line::default(),
+        Line::from(format!("{}, {}", path.display(), error)),
+    ];
+    if let Some((line, column)) = error.location {
+        if let Some(text) = content.lines().nth(line - 1) {
+            let number = format!("{} | ", line);
+            lines.push(Line::from(format!("{}{}", number, text)));
+            let indent = " ".repeat(number.len() + column - 1);
+            lines.push(Line::from(Span::styled(
+                format!("{}^", indent),
+                Style::default().fg(config.theme.danger),
+            )));
+        }
+    }
+    lines.push(Line::default());
+    lines.push(Line::from(
+        match backup::list(&config.data_dir, goal)?.first() {
+            Some(backup) => format!(
+                "Newest backup: {}",
+                backup.taken_at.format("%Y-%m-%d %H:%M")
+            ),
+            None => "There are no backups of this goal yet.".to_string(),
+        },
+    ));
+    let mut message: Option<String> = None;
+    loop {
+        terminal.draw(|f| render_recovery(f, &lines, message.as_deref(), &config.theme))?;
+        let Some(key) = input::read_key()? else {
+            continue;
+        };
+        match key.code {
+            KeyCode::Char('e') if crypto::is_encrypted(&content) => {
+                message = Some("The file is encrypted; restore a backup instead".to_string());
+            }
+            KeyCode::Char('e') => match suspend_tui(terminal, || open_in_editor(config, &path)) {
+                Ok(()) => return Ok(Recovery::Edited),
+                Err(e) => message = Some(format!("Failed to open the editor: {}", e)),
+            },
+            KeyCode::Char('b') => match backup::restore_latest(&config.data_dir, goal) {
+                Ok(Some(backup)) => return Ok(Recovery::Restored(backup)),
+                Ok(None) => message = Some("There is no readable backup to restore".to_string()),
+                Err(e) => message = Some(format!("Restoring the backup failed: {}", e)),
+            },
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(Recovery::Quit),
+            _ => {}
+        }
+    }
+}
 
+/// Opens `path` in the configured editor and waits for it to exit.
+fn open_in_editor(config: &Config, path: &Path) -> io::Result<()> {
+    let editor = config.editor_command();
+    Command::new(&editor[0])
+        .args(&editor[1..])
+        .arg(path)
+        .status()?;
     Ok(())
 }
 
@@ -56,9 +348,108 @@ fn run_app<B: ratatui::backend::Backend>(
     app: &mut App,
 ) -> io::Result<()> {
     loop {
+        app.tick();
         terminal.draw(|f| ui(f, app))?;
 
-        match input::handle_input()? {
+        let Some(key) = input::read_key()? else {
+            continue;
+        };
+        app.message = None;
+        if app.popup.is_some() {
+            app.close_popup();
+            continue;
+        }
+        if app.task_manager.conflict {
+            match key.code {
+                KeyCode::Char('r') => app.resolve_conflict(false),
+                KeyCode::Char('m') => app.resolve_conflict(true),
+                KeyCode::Esc => app.task_manager.conflict = false,
+                _ => {}
+            }
+            continue;
+        }
+        if let Some(prompt) = &mut app.prompt {
+            match key.code {
+                KeyCode::Enter => app.submit_prompt(),
+                KeyCode::Esc => app.cancel_prompt(),
+                KeyCode::Backspace => {
+                    prompt.input.pop();
+                }
+                KeyCode::Char(c) => prompt.input.push(c),
+                _ => {}
+            }
+            continue;
+        }
+
+        let event = app.keymap.event(key.code, key.modifiers);
+        if app.goal_picker.is_some() {
+            match event {
+                InputEvent::Quit | InputEvent::OpenGoals => app.close_goal_picker(),
+                InputEvent::NextTask => app.next_goal(),
+                InputEvent::PreviousTask => app.previous_goal(),
+                InputEvent::Select => app.select_goal(),
+                InputEvent::NewTask => app.new_goal(),
+                _ => {}
+            }
+            continue;
+        }
+
+        if app.is_previewing() {
+            match event {
+                InputEvent::Quit | InputEvent::ToggleHistory => app.end_preview(),
+                InputEvent::Restore => app.restore_version(),
+                InputEvent::NextTask => app.next_task(),
+                InputEvent::PreviousTask => app.previous_task(),
+                InputEvent::NextUndoneTask => app.next_undone_task(),
+                InputEvent::FirstUndone => app.first_undone_task(),
+                InputEvent::LastTask => app.last_task(),
+                InputEvent::NextChecklistItem => app.next_checklist_item(),
+                InputEvent::PreviousChecklistItem => app.previous_checklist_item(),
+                _ => {}
+            }
+            continue;
+        }
+
+        if app.view == View::History {
+            let handled = match event {
+                InputEvent::NextTask => {
+                    app.next_version();
+                    true
+                }
+                InputEvent::PreviousTask => {
+                    app.previous_version();
+                    true
+                }
+                InputEvent::Select => {
+                    app.preview_version();
+                    true
+                }
+                InputEvent::Restore => {
+                    app.restore_version();
+                    true
+                }
+                _ => false,
+            };
+            if handled {
+                continue;
+            }
+        }
+
+        if app.view != View::Tasks
+            && !matches!(
+                event,
+                InputEvent::Quit
+                    | InputEvent::ToggleStats
+                    | InputEvent::ToggleHeatmap
+                    | InputEvent::ToggleHistory
+                    | InputEvent::ToggleHelp
+                    | InputEvent::OpenGoals
+            )
+        {
+            continue;
+        }
+
+        match event {
             InputEvent::Quit => app.quit(),
             InputEvent::MarkDone => app.mark_done(),
             InputEvent::MarkUndone => app.mark_undone(),
@@ -67,43 +458,25 @@ fn run_app<B: ratatui::backend::Backend>(
             InputEvent::NextUndoneTask => app.next_undone_task(),
             InputEvent::FirstUndone => app.first_undone_task(),
             InputEvent::LastTask => app.last_task(),
-            InputEvent::NewTask => {
-                terminal.set_cursor_position(ratatui::layout::Position::from((0, 0)))?;
-                execute!(io::stdout(), LeaveAlternateScreen)?;
-                disable_raw_mode()?;
-                app.new_task()?;
-                enable_raw_mode()?;
-                execute!(io::stdout(), EnterAlternateScreen)?;
-                terminal.clear()?;
-            }
-            InputEvent::EditTask => {
-                terminal.set_cursor_position(ratatui::layout::Position::from((0, 0)))?;
-                execute!(io::stdout(), LeaveAlternateScreen)?;
-                disable_raw_mode()?;
-                app.edit_task()?;
-                enable_raw_mode()?;
-                execute!(io::stdout(), EnterAlternateScreen)?;
-                terminal.clear()?;
-            }
-            InputEvent::GitPush => {
-                terminal.set_cursor_position(ratatui::layout::Position::from((0, 0)))?;
-                execute!(io::stdout(), LeaveAlternateScreen)?;
-                disable_raw_mode()?;
-                app.git_push()?;
-                enable_raw_mode()?;
-                execute!(io::stdout(), EnterAlternateScreen)?;
-                terminal.clear()?;
-            }
-            InputEvent::GitPull => {
-                terminal.set_cursor_position(ratatui::layout::Position::from((0, 0)))?;
-                execute!(io::stdout(), LeaveAlternateScreen)?;
-                disable_raw_mode()?;
-                app.git_pull()?;
-                enable_raw_mode()?;
-                execute!(io::stdout(), EnterAlternateScreen)?;
-                terminal.clear()?;
-            }
-            InputEvent::Noop => {}
+            InputEvent::NewTask => suspend_tui(terminal, || {
+                app.new_task();
+                Ok(())
+            })?,
+            InputEvent::EditTask => suspend_tui(terminal, || {
+                app.edit_task();
+                Ok(())
+            })?,
+            InputEvent::GitPush => app.push_tasks(),
+            InputEvent::GitPull => app.pull_tasks(),
+            InputEvent::OpenGoals => app.open_goal_picker(),
+            InputEvent::ToggleStats => app.toggle_stats(),
+            InputEvent::ToggleHeatmap => app.toggle_heatmap(),
+            InputEvent::ToggleHistory => app.toggle_history(),
+            InputEvent::ToggleHelp => app.toggle_help(),
+            InputEvent::NextChecklistItem => app.next_checklist_item(),
+            InputEvent::PreviousChecklistItem => app.previous_checklist_item(),
+            InputEvent::ToggleChecklistItem => app.toggle_checklist_item(),
+            InputEvent::Restore | InputEvent::Select | InputEvent::Noop => {}
         }
 
         if app.should_quit {
@@ -112,31 +485,69 @@ fn run_app<B: ratatui::backend::Backend>(
     }
 }
 
+/// Leaves the alternate screen while `f` runs, e.g. to open an editor.
+fn suspend_tui<B, F>(terminal: &mut Terminal<B>, f: F) -> io::Result<()>
+where
+    B: ratatui::backend::Backend,
+    F: FnOnce() -> io::Result<()>,
+{
+    terminal.set_cursor_position(ratatui::layout::Position::from((0, 0)))?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    let res = f();
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    terminal.clear()?;
+    res
+}
+
 /// Renders the UI.
 fn ui(f: &mut Frame, app: &App) {
+    let theme = &app.config.theme;
     let size = f.area();
     let current_task = app.task_manager.current_task();
     let total = app.task_manager.tasks.len();
     let done_count = app.task_manager.tasks.iter().filter(|t| t.done).count();
     let undone_count = total - done_count;
-    let percent_done = if total > 0 {
-        (done_count * 100) / total
+    let percent_done = (app.task_manager.progress() * 100.0) as usize;
+
+    let task_status = if current_task.done {
+        "✅"
+    } else if app.task_manager.is_blocked(app.task_manager.current_index) {
+        "🔒"
     } else {
-        0
+        "❌"
     };
 
-    let task_status = if current_task.done { "✅" } else { "❌" };
-
     let header_text = format!("{} Task {} of {}:", task_status, current_task.id, total);
 
-    let progress_text = format!(
+    let today = Local::now().date_naive();
+    let overdue_count = app
+        .task_manager
+        .tasks
+        .iter()
+        .filter(|t| t.is_overdue(today))
+        .count();
+    let mut progress_text = format!(
         "Progress: {}/{} done | {} undone",
         done_count, total, undone_count
     );
+    if overdue_count > 0 {
+        progress_text.push_str(&format!(" | {} overdue", overdue_count));
+    }
+    let (current_streak, longest_streak) = app.task_manager.streaks(today);
+    progress_text.push_str(&format!(
+        " | Streak: {} {} (best: {})",
+        current_streak,
+        if current_streak == 1 { "day" } else { "days" },
+        longest_streak
+    ));
 
     let title_text = current_task.title.to_string();
-
-    let footer_text = "[d]:mark done / [u]:mark undone / [k]:prev / [j]:next / [N]:next undone / [f]:first undone / [l]:last / [n]:new / [e]:edit / [p]:pull / [P]:push / [q]:quit";
+    let mut title_style = Style::default().add_modifier(Modifier::BOLD);
+    if current_task.is_overdue(today) {
+        title_style = title_style.fg(theme.danger);
+    }
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -151,10 +562,7 @@ fn ui(f: &mut Frame, app: &App) {
     let empty_width = available_width - filled_width;
     let progress_bar_line = Line::from(vec![
         Span::raw("["),
-        Span::styled(
-            "#".repeat(filled_width),
-            Style::default().fg(Color::LightGreen),
-        ),
+        Span::styled("#".repeat(filled_width), Style::default().fg(theme.accent)),
         Span::raw("-".repeat(empty_width)),
         Span::raw(format!("] {}%", percent_done)),
     ]);
@@ -165,23 +573,650 @@ fn ui(f: &mut Frame, app: &App) {
         Line::from("=============================="),
         Line::from(progress_text),
         progress_bar_line,
+    ];
+    if let (Some(target), Some(days)) = (
+        app.task_manager.target_date,
+        app.task_manager.days_remaining(),
+    ) {
+        let deadline_text = match days {
+            0 => format!("Deadline: {} (today)", target),
+            d if d > 0 => format!("Deadline: {} ({} days left)", target, d),
+            d => format!("Deadline: {} ({} days overdue)", target, -d),
+        };
+        let deadline_style = if days < 0 {
+            Style::default().fg(theme.danger)
+        } else {
+            Style::default()
+        };
+        main_content.push(Line::from(deadline_text).style(deadline_style));
+    }
+    main_content.extend([
         Line::from("=============================="),
         Line::from(""),
         Line::from(""),
-        Line::from(title_text).style(Style::default().add_modifier(Modifier::BOLD)),
-        Line::from(""),
+        Line::from(title_text).style(title_style),
         Line::from(""),
-    ];
+    ]);
+    let mut dates = Vec::new();
+    if let Some(due) = current_task.due {
+        dates.push(format!("Due: {}", due));
+    }
+    if let Some(start) = current_task.start_after {
+        if !current_task.is_started(today) {
+            dates.push(format!("Starts: {}", start));
+        }
+    }
+    if !dates.is_empty() {
+        let dates_style = if current_task.is_overdue(today) {
+            Style::default().fg(theme.danger)
+        } else {
+            Style::default().fg(theme.muted)
+        };
+        main_content.push(Line::from(dates.join(" | ")).style(dates_style));
+    }
+    main_content.push(Line::from(""));
+    let blockers = app.task_manager.blockers(app.task_manager.current_index);
+    if !current_task.done && !blockers.is_empty() {
+        let blocked_by: Vec<String> = blockers
+            .iter()
+            .map(|t| format!("#{} {}", t.id, t.title))
+            .collect();
+        main_content.push(
+            Line::from(format!("Blocked by: {}", blocked_by.join(", ")))
+                .style(Style::default().fg(theme.danger)),
+        );
+        main_content.push(Line::from(""));
+    }
     main_content.extend(current_task.description.split('\n').map(Line::from));
+    if !current_task.checklist.is_empty() {
+        let checked = current_task.checklist.iter().filter(|i| i.done).count();
+        main_content.push(Line::from(""));
+        main_content.push(Line::from(format!(
+            "Checklist: {}/{}",
+            checked,
+            current_task.checklist.len()
+        )));
+        for (i, item) in current_task.checklist.iter().enumerate() {
+            let mark = if item.done { "[x]" } else { "[ ]" };
+            let mut style = Style::default();
+            if item.done {
+                style = style.fg(theme.muted);
+            }
+            if i == app.checklist_index {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            main_content.push(Line::from(format!("{} {}", mark, item.title)).style(style));
+        }
+    }
 
+    let title = match app.selected_version().filter(|_| app.is_previewing()) {
+        Some(entry) => Span::styled(
+            format!(
+                "{} - preview of {} ({})",
+                app.task_manager.the_goal,
+                entry.commit.id,
+                entry.commit.date.format("%Y-%m-%d %H:%M")
+            ),
+            Style::default().fg(theme.warning),
+        ),
+        None => Span::raw(app.task_manager.the_goal.as_str()),
+    };
     let main_paragraph = Paragraph::new(main_content).block(
         Block::default()
             .borders(Borders::ALL)
-            .title(app.task_manager.the_goal.as_str()),
+            .title(title)
+            .title(sync_status(app).right_aligned()),
     );
-    f.render_widget(main_paragraph, chunks[0]);
+    match app.view {
+        View::Tasks => f.render_widget(main_paragraph, chunks[0]),
+        View::Stats => render_stats(f, app, chunks[0]),
+        View::Heatmap => render_heatmap(f, app, chunks[0]),
+        View::Help => render_help(f, app, chunks[0]),
+        View::History => render_history(f, app, chunks[0]),
+    }
 
-    let footer_paragraph =
-        Paragraph::new(footer_text).block(Block::default().borders(Borders::ALL));
+    let keymap = &app.keymap;
+    let footer_text = if app.popup.is_some() {
+        "any key: close".to_string()
+    } else if app.task_manager.conflict {
+        "r: reload | m: merge | Esc: decide later".to_string()
+    } else if app.prompt.is_some() {
+        "Enter: submit | Esc: cancel".to_string()
+    } else if app.goal_picker.is_some() {
+        keymap.hints(&[
+            (InputEvent::PreviousTask, "prev"),
+            (InputEvent::NextTask, "next"),
+            (InputEvent::Select, "switch"),
+            (InputEvent::NewTask, "new goal"),
+            (InputEvent::Quit, "close"),
+        ])
+    } else if app.is_previewing() {
+        keymap.hints(&[
+            (InputEvent::Restore, "restore this version"),
+            (InputEvent::PreviousTask, "prev"),
+            (InputEvent::NextTask, "next"),
+            (InputEvent::Quit, "back to history"),
+        ])
+    } else {
+        match app.view {
+            View::Tasks => keymap.footer(),
+            View::Stats => keymap.hints(&[
+                (InputEvent::ToggleStats, "back to tasks"),
+                (InputEvent::ToggleHeatmap, "heatmap"),
+                (InputEvent::Quit, "quit"),
+            ]),
+            View::Heatmap => keymap.hints(&[
+                (InputEvent::ToggleHeatmap, "back to tasks"),
+                (InputEvent::ToggleStats, "stats"),
+                (InputEvent::Quit, "quit"),
+            ]),
+            View::Help => keymap.hints(&[
+                (InputEvent::ToggleHelp, "back to tasks"),
+                (InputEvent::Quit, "quit"),
+            ]),
+            View::History => keymap.hints(&[
+                (InputEvent::PreviousTask, "newer"),
+                (InputEvent::NextTask, "older"),
+                (InputEvent::Select, "preview"),
+                (InputEvent::Restore, "restore"),
+                (InputEvent::ToggleHistory, "back to tasks"),
+                (InputEvent::Quit, "quit"),
+            ]),
+        }
+    };
+    let mut footer_block = Block::default().borders(Borders::ALL);
+    if let Some(message) = &app.message {
+        footer_block = footer_block.title(Span::styled(
+            format!(" {} ", message),
+            Style::default().fg(theme.warning),
+        ));
+    }
+    let footer_paragraph = Paragraph::new(footer_text).block(footer_block);
     f.render_widget(footer_paragraph, chunks[1]);
+
+    if let Some(picker) = &app.goal_picker {
+        render_goal_picker(f, picker, &app.goal, chunks[0]);
+    }
+    if let Some(prompt) = &app.prompt {
+        render_prompt(f, prompt, chunks[0]);
+    }
+    if app.task_manager.conflict {
+        render_popup(f, &conflict_popup(), theme.warning, chunks[0]);
+    }
+    if let Some(popup) = &app.popup {
+        render_popup(f, popup, theme.danger, chunks[0]);
+    }
+}
+
+/// Returns the popup asking how to resolve tasks changed on disk by another program.
+fn conflict_popup() -> Popup {
+    Popup {
+        title: "Tasks changed on disk".to_string(),
+        body: format!(
+            "Another program changed {}, so your changes were not saved.\n\n\
+             r: reload the tasks from disk, dropping your changes\n\
+             m: merge your changes into the tasks on disk\n\
+             Esc: decide later; nothing is saved until then",
+            persistence::TASKS_FILE
+        ),
+    }
+}
+
+/// Frames of the spinner shown while syncing.
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Returns the progress of a running sync, or how the goal compares to its remote.
+fn sync_status(app: &App) -> Line<'static> {
+    match &app.sync {
+        Some(worker) => {
+            let frame = (Local::now().timestamp_millis() / 100) as usize % SPINNER.len();
+            Line::from(format!(
+                " {} {}: {}... ",
+                SPINNER[frame],
+                worker.action.name(),
+                worker.step
+            ))
+        }
+        None => {
+            let mut parts = Vec::new();
+            if let Some(status) = app.repo_status {
+                let mut part = format!("↑{} ↓{}", status.ahead, status.behind);
+                if status.dirty {
+                    part.push_str(" modified");
+                }
+                parts.push(part);
+            }
+            if let Some(time) = app.last_synced {
+                parts.push(format!("Last synced {}", time.format("%H:%M")));
+            }
+            if parts.is_empty() {
+                Line::default()
+            } else {
+                Line::from(format!(" {} ", parts.join(" | ")))
+            }
+        }
+    }
+}
+
+/// Renders the statistics screen into `area`.
+fn render_stats(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.config.theme;
+    let today = Local::now().date_naive();
+    let stats = Stats::compute(&app.task_manager, today);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("{} - Statistics", app.task_manager.the_goal));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(5),      // Summary
+            Constraint::Percentage(55), // Burndown
+            Constraint::Min(0),         // Completions
+        ])
+        .split(inner);
+
+    let average = stats
+        .average_days_per_task
+        .map(|days| format!("{:.1} days", days))
+        .unwrap_or_else(|| "n/a".to_string());
+    let projection = match (stats.remaining, stats.projected_finish) {
+        (0, _) => "all tasks done".to_string(),
+        (_, Some(date)) => match app.task_manager.target_date {
+            Some(target) if date > target => {
+                format!(
+                    "{} ({} days after the deadline)",
+                    date,
+                    (date - target).num_days()
+                )
+            }
+            _ => date.to_string(),
+        },
+        (_, None) => "n/a (nothing done in the last 4 weeks)".to_string(),
+    };
+    let summary = vec![
+        Line::from(format!("Remaining tasks: {}", stats.remaining)),
+        Line::from(format!("Average days per task: {}", average)),
+        Line::from(format!(
+            "Velocity (last 4 weeks): {:.1} tasks/week",
+            stats.velocity * 7.0
+        )),
+        Line::from(format!("Projected finish: {}", projection)),
+    ];
+    f.render_widget(Paragraph::new(summary), rows[0]);
+
+    let burndown: Vec<(f64, f64)> = stats
+        .burndown
+        .iter()
+        .enumerate()
+        .map(|(i, (_, undone))| (i as f64, *undone as f64))
+        .collect();
+    let mut projection_line = Vec::new();
+    let mut x_max = burndown.len().saturating_sub(1) as f64;
+    if let Some(date) = stats.projected_finish {
+        let end = x_max + (date - today).num_days() as f64;
+        projection_line = vec![(x_max, stats.remaining as f64), (end, 0.0)];
+        x_max = end;
+    }
+    let y_max = stats
+        .burndown
+        .iter()
+        .map(|(_, undone)| *undone)
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64;
+    let first_day = stats.burndown.first().map(|(day, _)| *day).unwrap_or(today);
+    let last_day = stats.projected_finish.unwrap_or(today);
+    let datasets = vec![
+        Dataset::default()
+            .name("undone")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(theme.accent))
+            .data(&burndown),
+        Dataset::default()
+            .name("projected")
+            .marker(symbols::Marker::Dot)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(theme.muted))
+            .data(&projection_line),
+    ];
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::TOP).title("Burndown"))
+        .x_axis(
+            Axis::default()
+                .bounds([0.0, x_max.max(1.0)])
+                .labels([first_day.to_string(), last_day.to_string()]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, y_max])
+                .labels(["0".to_string(), format!("{}", y_max)]),
+        );
+    f.render_widget(chart, rows[1]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[2]);
+
+    let bars: Vec<Bar> = stats
+        .weekly_completions
+        .iter()
+        .map(|(week, count)| {
+            Bar::default()
+                .value(*count)
+                .label(Line::from(week.format("%d/%m").to_string()))
+        })
+        .collect();
+    let bar_chart = BarChart::default()
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .title("Completions per week"),
+        )
+        .data(BarGroup::default().bars(&bars))
+        .bar_width(5)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(theme.accent));
+    f.render_widget(bar_chart, columns[0]);
+
+    let sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .borders(Borders::TOP)
+                .title("Completions per day (last 60 days)"),
+        )
+        .data(&stats.daily_completions)
+        .style(Style::default().fg(theme.accent));
+    f.render_widget(sparkline, columns[1]);
+}
+
+/// Renders the keybindings of every action into `area`.
+fn render_help(f: &mut Frame, app: &App, area: Rect) {
+    let help = app.keymap.help();
+    let width = help
+        .iter()
+        .map(|(keys, _)| keys.chars().count())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Line> = help
+        .into_iter()
+        .map(|(keys, description)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<width$}  ", keys, width = width),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(description),
+            ])
+        })
+        .collect();
+    let paragraph =
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Keybindings"));
+    f.render_widget(paragraph, area);
+}
+
+/// Shades of the completion heatmap, from no completions to the most.
+const HEATMAP_SHADES: [Color; 5] = [
+    Color::DarkGray,
+    Color::Rgb(14, 68, 41),
+    Color::Rgb(0, 109, 50),
+    Color::Rgb(38, 166, 65),
+    Color::Rgb(57, 211, 83),
+];
+
+/// Renders the completion heatmap of the last year into `area`.
+fn render_heatmap(f: &mut Frame, app: &App, area: Rect) {
+    let today = Local::now().date_naive();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("{} - Completed tasks", app.task_manager.the_goal));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    // Each week takes two columns after the four columns of weekday labels.
+    let weeks = ((inner.width as usize).saturating_sub(4) / 2).clamp(1, stats::HEATMAP_WEEKS);
+    let heatmap = stats::heatmap(&app.task_manager.completion_log, today, weeks);
+    let days: Vec<(NaiveDate, u32)> = heatmap.iter().flatten().flatten().copied().collect();
+    let max = days.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let total: u32 = days.iter().map(|(_, count)| count).sum();
+    let active_days = days.iter().filter(|(_, count)| *count > 0).count();
+
+    let mut months = vec![' '; 4 + 2 * heatmap.len()];
+    let mut previous_month = None;
+    let mut next_free = 0;
+    for (i, week) in heatmap.iter().enumerate() {
+        let Some((monday, _)) = week[0] else {
+            continue;
+        };
+        let column = 4 + 2 * i;
+        if previous_month != Some(monday.month()) && column >= next_free {
+            let label = monday.format("%b").to_string();
+            for (offset, c) in label.chars().enumerate() {
+                if let Some(slot) = months.get_mut(column + offset) {
+                    *slot = c;
+                }
+            }
+            next_free = column + label.len() + 1;
+        }
+        previous_month = Some(monday.month());
+    }
+    let months: String = months.into_iter().collect();
+
+    let mut lines = vec![
+        Line::from(format!(
+            "{} tasks done on {} days over the last {} weeks",
+            total,
+            active_days,
+            heatmap.len()
+        )),
+        Line::from(""),
+        Line::from(months),
+    ];
+    for (weekday, label) in ["Mon", "", "Wed", "", "Fri", "", "Sun"].iter().enumerate() {
+        let mut spans = vec![Span::raw(format!("{:<4}", label))];
+        for week in &heatmap {
+            spans.push(match week[weekday] {
+                Some((_, count)) => {
+                    let shade = if count == 0 {
+                        0
+                    } else {
+                        (count * 4).div_ceil(max) as usize
+                    };
+                    Span::styled("■ ", Style::default().fg(HEATMAP_SHADES[shade]))
+                }
+                None => Span::raw("  "),
+            });
+        }
+        lines.push(Line::from(spans));
+    }
+    let mut legend = vec![Span::raw("Less ")];
+    legend.extend(
+        HEATMAP_SHADES
+            .iter()
+            .map(|color| Span::styled("■ ", Style::default().fg(*color))),
+    );
+    legend.push(Span::raw("More"));
+    lines.push(Line::from(""));
+    lines.push(Line::from(legend));
+
+    f.render_widget(Paragraph::new(lines), inner);
+}
+
+/// Renders the goal picker as a popup over `area`.
+fn render_goal_picker(f: &mut Frame, picker: &GoalPicker, active: &str, area: Rect) {
+    let items: Vec<ListItem> = picker
+        .goals
+        .iter()
+        .map(|goal| {
+            let marker = if goal.name == active { "* " } else { "  " };
+            ListItem::new(format!(
+                "{}{} ({}/{} done) [{}]",
+                marker, goal.title, goal.done, goal.total, goal.name
+            ))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Goals"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(picker.selected));
+
+    let popup = centered_rect(area, 60, (picker.goals.len() as u16).saturating_add(2));
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(list, popup, &mut state);
+}
+
+/// Renders a single-line text prompt over `area`.
+fn render_prompt(f: &mut Frame, prompt: &Prompt, area: Rect) {
+    let popup = centered_rect(area, 60, 3);
+    let paragraph = Paragraph::new(prompt.input.as_str()).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(prompt.label.as_str()),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(paragraph, popup);
+    let cursor_x = popup.x + 1 + prompt.input.chars().count() as u16;
+    f.set_cursor_position((cursor_x.min(popup.right().saturating_sub(2)), popup.y + 1));
+}
+
+/// Renders a popup with wrapped text over `area`.
+fn render_popup(f: &mut Frame, popup: &Popup, color: Color, area: Rect) {
+    let text = Text::from(popup.body.as_str());
+    let rect = centered_rect(area, 80, (text.height() as u16).saturating_add(2));
+    let paragraph = Paragraph::new(text).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(color))
+            .title(popup.title.as_str()),
+    );
+    f.render_widget(Clear, rect);
+    f.render_widget(paragraph, rect);
+}
+
+/// Renders the history of the task list into `area`.
+fn render_history(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.config.theme;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("{} - History", app.task_manager.the_goal));
+    let Some(history) = app.history.as_ref().filter(|h| !h.entries.is_empty()) else {
+        let text = "No history yet. Versions appear here once tasks are pushed or pulled.";
+        f.render_widget(Paragraph::new(text).block(block), area);
+        return;
+    };
+    let items: Vec<ListItem> = history
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut lines = vec![Line::from(vec![
+                Span::styled(
+                    format!("{} ", entry.commit.id),
+                    Style::default().fg(theme.accent),
+                ),
+                Span::styled(
+                    format!("{} ", entry.commit.date.format("%Y-%m-%d %H:%M")),
+                    Style::default().fg(theme.muted),
+                ),
+                Span::raw(entry.commit.subject.as_str()),
+            ])];
+            if entry.summary != entry.commit.subject {
+                lines.push(Line::from(Span::styled(
+                    format!("    {}", entry.summary),
+                    Style::default().fg(theme.muted),
+                )));
+            }
+            ListItem::new(lines)
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(history.selected));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// Renders the first-run wizard.
+fn render_onboarding(f: &mut Frame, wizard: &Onboarding, theme: &Theme) {
+    let area = centered_rect(f.area(), 70, 12);
+    let mut lines = vec![
+        Line::from("Welcome to Tasklings! How do you want to start?"),
+        Line::default(),
+    ];
+    let (label, hints) = match wizard.step {
+        Step::Choose => {
+            for (i, choice) in onboarding::CHOICES.iter().enumerate() {
+                let mut style = Style::default();
+                if i == wizard.selected {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+                lines.push(Line::from(format!("  {}  ", choice)).style(style));
+            }
+            (None, "[j/k]:choose / [Enter]:select / [q]:quit")
+        }
+        Step::CloneUrl => (Some("Repository URL: "), "[Enter]:clone / [Esc]:back"),
+        Step::GoalTitle => (Some("Goal: "), "[Enter]:create / [Esc]:back"),
+    };
+    if let Some(label) = label {
+        lines.push(Line::from(vec![
+            Span::raw(label),
+            Span::styled(wizard.input.as_str(), Style::default().fg(theme.accent)),
+        ]));
+    }
+    lines.push(Line::default());
+    if let Some(message) = &wizard.message {
+        lines.push(Line::from(Span::styled(
+            message.as_str(),
+            Style::default().fg(theme.warning),
+        )));
+    }
+    lines.push(Line::from(Span::styled(
+        hints,
+        Style::default().fg(theme.muted),
+    )));
+
+    let paragraph = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title("Tasklings"));
+    f.render_widget(paragraph, area);
+}
+
+/// Renders the recovery screen with the description of the error in `lines`.
+fn render_recovery(f: &mut Frame, lines: &[Line], message: Option<&str>, theme: &Theme) {
+    let mut lines = lines.to_vec();
+    lines.push(Line::default());
+    if let Some(message) = message {
+        lines.push(Line::from(Span::styled(
+            message,
+            Style::default().fg(theme.warning),
+        )));
+    }
+    lines.push(Line::from(Span::styled(
+        "[e]:edit the file / [b]:restore the newest backup / [q]:quit",
+        Style::default().fg(theme.muted),
+    )));
+    let area = centered_rect(f.area(), 80, (lines.len() as u16).saturating_add(2));
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.danger))
+            .title("Tasks file is broken"),
+    );
+    f.render_widget(paragraph, area);
+}
+
+/// Returns a rectangle of `percent_x` width and `height` rows centered in `area`.
+fn centered_rect(area: Rect, percent_x: u16, height: u16) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
 }
@@ -0,0 +1,343 @@
+//! Three-way merge of task lists by task id.
+
+use crate::tasks::{ChecklistItem, Task, TaskList};
+use chrono::{DateTime, Local, NaiveDate};
+use std::collections::BTreeMap;
+
+/// Merges the task lists of two sides that diverged from `base`.
+///
+/// Tasks are matched by id. Tasks added on either side are kept, and a task
+/// added on both sides under the same id keeps its id on our side while the
+/// other one gets a new id, which their dependencies on it follow. A task
+/// deleted on one side is dropped unless the other side changed it. The done
+/// state of a task is taken from the side that changed it; if both did, it is
+/// taken from the latest change, and done wins if there are no timestamps.
+/// Other fields changed on both sides keep our value.
+pub fn merge(base: Option<&TaskList>, ours: &TaskList, theirs: &TaskList) -> TaskList {
+    let base_task = |id: i32| base.and_then(|b| b.tasks.iter().find(|t| t.id == id));
+    let mut next_id = ours
+        .tasks
+        .iter()
+        .chain(&theirs.tasks)
+        .map(|t| t.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+
+    // Renumber the tasks both sides added under the same id on their side.
+    let mut new_ids = BTreeMap::new();
+    for their_task in &theirs.tasks {
+        let collides = base_task(their_task.id).is_none()
+            && ours
+                .tasks
+                .iter()
+                .any(|t| t.id == their_task.id && t != their_task);
+        if collides {
+            new_ids.insert(their_task.id, next_id);
+            next_id += 1;
+        }
+    }
+    let new_id = |id: i32| new_ids.get(&id).copied().unwrap_or(id);
+    let their_tasks: Vec<Task> = theirs
+        .tasks
+        .iter()
+        .map(|task| Task {
+            id: new_id(task.id),
+            depends_on: task.depends_on.iter().map(|&id| new_id(id)).collect(),
+            ..task.clone()
+        })
+        .collect();
+
+    let mut tasks: Vec<Task> = Vec::new();
+    for our_task in &ours.tasks {
+        let original = base_task(our_task.id);
+        match their_tasks.iter().find(|t| t.id == our_task.id) {
+            Some(their_task) => tasks.push(merge_task(original, our_task, their_task)),
+            // Deleted on their side, kept only if we changed it.
+            None if original.is_some_and(|o| o == our_task) => {}
+            None => tasks.push(our_task.clone()),
+        }
+    }
+
+    let mut previous_id = None;
+    for their_task in &their_tasks {
+        let original = base_task(their_task.id);
+        let ours_task = ours.tasks.iter().find(|t| t.id == their_task.id);
+        let new_task = match ours_task {
+            Some(_) => None,
+            None if original.is_some_and(|o| o == their_task) => None,
+            None => Some(their_task.clone()),
+        };
+        if let Some(task) = new_task {
+            // Keep tasks added on their side after the task preceding them there.
+            let position = previous_id
+                .and_then(|id| tasks.iter().position(|t| t.id == id))
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            tasks.insert(position, task);
+        }
+        previous_id = Some(their_task.id);
+    }
+
+    TaskList {
+        tasks,
+        current_index: ours.current_index,
+        the_goal: merge_field(base.map(|b| &b.the_goal), &ours.the_goal, &theirs.the_goal),
+        target_date: merge_field(
+            base.map(|b| &b.target_date),
+            &ours.target_date,
+            &theirs.target_date,
+        ),
+        completion_log: merge_log(
+            base.map(|b| &b.completion_log),
+            &ours.completion_log,
+            &theirs.completion_log,
+        ),
+    }
+}
+
+/// Merges two versions of the same task.
+fn merge_task(base: Option<&Task>, ours: &Task, theirs: &Task) -> Task {
+    let mut task = Task {
+        id: ours.id,
+        title: merge_field(base.map(|b| &b.title), &ours.title, &theirs.title),
+        description: merge_field(
+            base.map(|b| &b.description),
+            &ours.description,
+            &theirs.description,
+        ),
+        done: ours.done,
+        depends_on: merge_field(
+            base.map(|b| &b.depends_on),
+            &ours.depends_on,
+            &theirs.depends_on,
+        ),
+        due: merge_field(base.map(|b| &b.due), &ours.due, &theirs.due),
+        start_after: merge_field(
+            base.map(|b| &b.start_after),
+            &ours.start_after,
+            &theirs.start_after,
+        ),
+        created_at: match (ours.created_at, theirs.created_at) {
+            (Some(o), Some(t)) => Some(o.min(t)),
+            (o, t) => o.or(t),
+        },
+        done_at: ours.done_at,
+        reopened_at: ours.reopened_at.max(theirs.reopened_at),
+        checklist: merge_checklist(
+            base.map(|b| b.checklist.as_slice()),
+            &ours.checklist,
+            &theirs.checklist,
+        ),
+    };
+    if ours.done != theirs.done {
+        let changed = |task: &Task| {
+            base.is_none_or(|b| {
+                (b.done, state_changed_at(b)) != (task.done, state_changed_at(task))
+            })
+        };
+        let theirs_wins = if !changed(ours) {
+            true
+        } else if !changed(theirs) {
+            false
+        } else {
+            match (state_changed_at(ours), state_changed_at(theirs)) {
+                (Some(o), Some(t)) => t > o,
+                (None, Some(_)) => true,
+                (Some(_), None) => false,
+                (None, None) => theirs.done,
+            }
+        };
+        if theirs_wins {
+            task.done = theirs.done;
+            task.done_at = theirs.done_at;
+        }
+    }
+    task
+}
+
+/// Returns when the task last changed its done state.
+fn state_changed_at(task: &Task) -> Option<DateTime<Local>> {
+    if task.done {
+        task.done_at
+    } else {
+        task.reopened_at
+    }
+}
+
+/// Merges a field, preferring our value if both sides changed it.
+fn merge_field<T: Clone + PartialEq>(base: Option<&T>, ours: &T, theirs: &T) -> T {
+    if base == Some(ours) {
+        theirs.clone()
+    } else {
+        ours.clone()
+    }
+}
+
+/// Merges checklists item by item, matching items by title.
+fn merge_checklist(
+    base: Option<&[ChecklistItem]>,
+    ours: &[ChecklistItem],
+    theirs: &[ChecklistItem],
+) -> Vec<ChecklistItem> {
+    if base == Some(ours) {
+        return theirs.to_vec();
+    }
+    if base == Some(theirs) || ours == theirs {
+        return ours.to_vec();
+    }
+    let mut items = ours.to_vec();
+    for their_item in theirs {
+        match items.iter_mut().find(|i| i.title == their_item.title) {
+            Some(item) => item.done |= their_item.done,
+            None => {
+                let removed_by_us =
+                    base.is_some_and(|b| b.iter().any(|i| i.title == their_item.title));
+                if !removed_by_us {
+                    items.push(their_item.clone());
+                }
+            }
+        }
+    }
+    items
+}
+
+/// Merges per-day completion counts by adding the completions of both sides.
+fn merge_log(
+    base: Option<&BTreeMap<NaiveDate, u32>>,
+    ours: &BTreeMap<NaiveDate, u32>,
+    theirs: &BTreeMap<NaiveDate, u32>,
+) -> BTreeMap<NaiveDate, u32> {
+    let count =
+        |log: &BTreeMap<NaiveDate, u32>, day: &NaiveDate| log.get(day).copied().unwrap_or(0);
+    ours.keys()
+        .chain(theirs.keys())
+        .map(|day| {
+            let (o, t) = (count(ours, day), count(theirs, day));
+            let merged = match base {
+                Some(base) => (o + t).saturating_sub(count(base, day)),
+                None => o.max(t),
+            };
+            (*day, merged)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(list: &TaskList) -> Vec<i32> {
+        list.tasks.iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn renumbers_tasks_added_on_both_sides_under_the_same_id() {
+        let base = TaskList::for_test(&["First"]);
+        let mut ours = base.clone();
+        ours.tasks.push(Task::new(2, "Ours", ""));
+        let mut theirs = base.clone();
+        theirs.tasks[0].depends_on = vec![2];
+        theirs.tasks.push(Task::new(2, "Theirs", ""));
+        let mut their_next = Task::new(3, "Theirs next", "");
+        their_next.depends_on = vec![2];
+        theirs.tasks.push(their_next);
+
+        let merged = merge(Some(&base), &ours, &theirs);
+
+        assert_eq!(ids(&merged), vec![1, 4, 3, 2]);
+        let task = |id: i32| merged.tasks.iter().find(|t| t.id == id).unwrap();
+        assert_eq!(task(2).title, "Ours");
+        assert_eq!(task(4).title, "Theirs");
+        assert_eq!(task(3).depends_on, vec![4]);
+        assert_eq!(task(1).depends_on, vec![4]);
+    }
+
+    #[test]
+    fn identical_additions_are_kept_once() {
+        let added = TaskList::for_test(&["Same"]);
+        let merged = merge(None, &added, &added);
+        assert_eq!(ids(&merged), vec![1]);
+    }
+
+    #[test]
+    fn deleted_tasks_are_kept_if_edited_on_the_other_side() {
+        let base = TaskList::for_test(&["First", "Second"]);
+        let mut deleted = base.clone();
+        deleted.tasks.pop();
+        let mut edited = base.clone();
+        edited.tasks[1].title = "Second, edited".to_string();
+
+        let merged = merge(Some(&base), &deleted, &edited);
+        assert_eq!(ids(&merged), vec![1, 2]);
+        assert_eq!(merged.tasks[1].title, "Second, edited");
+
+        let merged = merge(Some(&base), &edited, &deleted);
+        assert_eq!(ids(&merged), vec![1, 2]);
+
+        let merged = merge(Some(&base), &base, &deleted);
+        assert_eq!(ids(&merged), vec![1]);
+    }
+
+    #[test]
+    fn merges_fields_edited_on_both_sides() {
+        let mut base = TaskList::for_test(&["Title"]);
+        base.tasks[0].description = "Description".to_string();
+        let mut ours = base.clone();
+        ours.tasks[0].title = "Our title".to_string();
+        ours.tasks[0].description = "Our description".to_string();
+        let mut theirs = base.clone();
+        let their_task = &mut theirs.tasks[0];
+        their_task.description = "Their description".to_string();
+        their_task.due = NaiveDate::from_ymd_opt(2025, 3, 1);
+        their_task.done = true;
+        their_task.done_at = Some(Local::now());
+
+        let merged = merge(Some(&base), &ours, &theirs);
+
+        let task = &merged.tasks[0];
+        assert_eq!(task.title, "Our title");
+        assert_eq!(task.description, "Our description");
+        assert_eq!(task.due, theirs.tasks[0].due);
+        assert!(task.done);
+        assert_eq!(task.done_at, theirs.tasks[0].done_at);
+    }
+
+    #[test]
+    fn latest_done_state_wins() {
+        let mut base = TaskList::for_test(&["Title"]);
+        base.tasks[0].done = true;
+        base.tasks[0].done_at = Some(Local::now() - chrono::Duration::hours(2));
+        let mut reopened = base.clone();
+        reopened.tasks[0].done = false;
+        reopened.tasks[0].reopened_at = Some(Local::now() - chrono::Duration::hours(1));
+        let mut edited = base.clone();
+        edited.tasks[0].title = "Edited".to_string();
+
+        let merged = merge(Some(&base), &edited, &reopened);
+
+        assert!(!merged.tasks[0].done);
+        assert_eq!(merged.tasks[0].title, "Edited");
+    }
+
+    #[test]
+    fn done_state_changed_on_one_side_wins() {
+        let mut base = TaskList::for_test(&["Title"]);
+        base.tasks[0].done = true;
+        base.tasks[0].done_at = Some(Local::now());
+        let mut reopened = base.clone();
+        reopened.tasks[0].done = false;
+
+        let merged = merge(Some(&base), &base, &reopened);
+        assert!(!merged.tasks[0].done);
+        let merged = merge(Some(&base), &reopened, &base);
+        assert!(!merged.tasks[0].done);
+
+        reopened.tasks[0].reopened_at = Some(Local::now());
+        let mut done_again = base.clone();
+        done_again.tasks[0].done_at = Some(Local::now());
+        let merged = merge(Some(&base), &reopened, &done_again);
+        assert!(merged.tasks[0].done);
+    }
+}
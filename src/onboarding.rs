@@ -0,0 +1,139 @@
+//! First-run setup of the data directory.
+
+use crate::git;
+use crate::persistence;
+use std::io;
+use std::path::Path;
+
+/// How to set up the first goal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Setup {
+    /// Clone an existing tasks repository from the given URL.
+    Clone(String),
+    /// Start a goal with the given title and a single starter task.
+    Blank(String),
+    /// Start the default goal with the demo tasks.
+    Demo,
+}
+
+/// Returns `true` if `data_dir` holds no goals yet.
+pub fn is_first_run(data_dir: &Path) -> io::Result<bool> {
+    Ok(persistence::list_goals(data_dir)?.is_empty())
+}
+
+/// Sets up a goal in `data_dir`, makes it the active goal and returns its name.
+///
+/// A cloned repository becomes `goal`, or the default goal if `None`.
+pub fn apply(data_dir: &Path, setup: &Setup, goal: Option<&str>) -> io::Result<String> {
+    let goal = match setup {
+        Setup::Clone(url) => {
+            let goal = goal.unwrap_or(persistence::DEFAULT_GOAL).to_string();
+            let dir = persistence::get_goal_dir(data_dir, &goal);
+            ensure_new(&dir, &goal)?;
+            git::clone(url, &dir)?;
+            goal
+        }
+        Setup::Blank(title) => persistence::create_goal(data_dir, title)?,
+        Setup::Demo => {
+            let goal = goal.unwrap_or(persistence::DEFAULT_GOAL).to_string();
+            let dir = persistence::get_goal_dir(data_dir, &goal);
+            ensure_new(&dir, &goal)?;
+            persistence::load_tasks(&dir)?;
+            goal
+        }
+    };
+    persistence::persist_active_goal(data_dir, &goal)?;
+    Ok(goal)
+}
+
+/// Fails if the goal directory `dir` already has content.
+fn ensure_new(dir: &Path, goal: &str) -> io::Result<()> {
+    if dir.exists() && dir.read_dir()?.next().is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("Goal '{}' already exists", goal),
+        ));
+    }
+    Ok(())
+}
+
+/// A step of the onboarding wizard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Step {
+    /// Choosing how to set up the first goal.
+    #[default]
+    Choose,
+    /// Entering the repository URL to clone.
+    CloneUrl,
+    /// Entering the title of a blank goal.
+    GoalTitle,
+}
+
+/// Choices offered by the wizard, in display order.
+pub const CHOICES: [&str; 3] = [
+    "Clone an existing tasks repository",
+    "Start a blank goal",
+    "Try the demo tasks",
+];
+
+/// State of the onboarding wizard shown on first run.
+#[derive(Default)]
+pub struct Onboarding {
+    /// The current step.
+    pub step: Step,
+    /// Index of the highlighted choice.
+    pub selected: usize,
+    /// The text entered so far.
+    pub input: String,
+    /// A message to show, e.g. why the last attempt failed.
+    pub message: Option<String>,
+}
+
+impl Onboarding {
+    /// Highlights the next choice.
+    pub fn next(&mut self) {
+        if self.selected + 1 < CHOICES.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Highlights the previous choice.
+    pub fn previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Moves past the current step and returns the setup once it is complete.
+    pub fn submit(&mut self) -> Option<Setup> {
+        self.message = None;
+        match self.step {
+            Step::Choose => match self.selected {
+                0 => self.step = Step::CloneUrl,
+                1 => self.step = Step::GoalTitle,
+                _ => return Some(Setup::Demo),
+            },
+            Step::CloneUrl | Step::GoalTitle => {
+                let input = self.input.trim().to_string();
+                if input.is_empty() {
+                    self.message = Some("Please enter a value".to_string());
+                    return None;
+                }
+                return Some(match self.step {
+                    Step::CloneUrl => Setup::Clone(input),
+                    _ => Setup::Blank(input),
+                });
+            }
+        }
+        None
+    }
+
+    /// Goes back to choosing, or returns `false` if already there.
+    pub fn back(&mut self) -> bool {
+        if self.step == Step::Choose {
+            return false;
+        }
+        self.step = Step::Choose;
+        self.input.clear();
+        self.message = None;
+        true
+    }
+}
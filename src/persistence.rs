@@ -1,41 +1,218 @@
 //! Handles persistence of application data.
 
+use crate::crypto;
 use crate::tasks::{Task, TaskList};
-use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
 
-/// Returns the path to the tasks directory.
-fn get_tasks_dir() -> PathBuf {
-    dirs::home_dir()
-        .expect("Could not find home directory")
-        .join(".tasks")
-        .join("tasks")
+/// Name of the goal used when no other goal has been selected.
+pub const DEFAULT_GOAL: &str = "tasks";
+
+/// Returns the path to the directory of the given goal inside `data_dir`.
+pub fn get_goal_dir(data_dir: &Path, goal: &str) -> PathBuf {
+    data_dir.join(goal)
+}
+
+/// Fails unless `goal` names a directory directly inside the data directory.
+pub fn validate_goal_name(goal: &str) -> io::Result<()> {
+    if goal.is_empty() || goal == "." || goal == ".." || goal.contains(std::path::is_separator) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid goal name '{}'", goal),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the directory of `goal`, failing if the goal has no tasks file.
+pub fn existing_goal_dir(data_dir: &Path, goal: &str) -> io::Result<PathBuf> {
+    validate_goal_name(goal)?;
+    let dir = get_goal_dir(data_dir, goal);
+    if !get_tasks_file(&dir).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No such goal '{}'", goal),
+        ));
+    }
+    Ok(dir)
 }
 
+/// Name of the tasks file inside a goal directory.
+pub const TASKS_FILE: &str = "tasks.toml";
+
 /// Returns the path to the tasks file.
-fn get_tasks_file() -> PathBuf {
-    get_tasks_dir().join("tasks.toml")
+pub fn get_tasks_file(dir: &Path) -> PathBuf {
+    dir.join(TASKS_FILE)
 }
 
+/// Name of the lock file inside a goal directory.
+const LOCK_FILE: &str = ".tasks.lock";
+
 /// Returns the path to the undone indexes file.
-fn get_undone_file() -> PathBuf {
-    get_tasks_dir().join("tasks_undone.toml")
+fn get_undone_file(dir: &Path) -> PathBuf {
+    dir.join("tasks_undone.toml")
+}
+
+/// Returns the path to the file remembering the last active goal.
+fn get_active_goal_file(data_dir: &Path) -> PathBuf {
+    data_dir.join(".active_goal")
+}
+
+/// Lists the names of all goals in `data_dir`, sorted alphabetically.
+pub fn list_goals(data_dir: &Path) -> io::Result<Vec<String>> {
+    if !data_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut goals: Vec<String> = fs::read_dir(data_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| get_tasks_file(&entry.path()).exists())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    goals.sort();
+    Ok(goals)
+}
+
+/// Loads the name of the last active goal.
+///
+/// Falls back to the default goal if the last active goal no longer exists, and
+/// to the first goal if the default goal doesn't exist either.
+pub fn load_active_goal(data_dir: &Path) -> String {
+    let exists = |goal: &String| existing_goal_dir(data_dir, goal).is_ok();
+    fs::read_to_string(get_active_goal_file(data_dir))
+        .ok()
+        .map(|content| content.trim().to_string())
+        .filter(exists)
+        .or_else(|| Some(DEFAULT_GOAL.to_string()).filter(exists))
+        .or_else(|| list_goals(data_dir).ok()?.into_iter().next())
+        .unwrap_or_else(|| DEFAULT_GOAL.to_string())
+}
+
+/// Persists the name of the active goal.
+pub fn persist_active_goal(data_dir: &Path, goal: &str) -> io::Result<()> {
+    fs::create_dir_all(data_dir)?;
+    fs::write(get_active_goal_file(data_dir), goal)
+}
+
+/// Creates a new goal with a single starter task and returns its name.
+pub fn create_goal(data_dir: &Path, title: &str) -> io::Result<String> {
+    let name: String = title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if name.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Goal name must contain at least one letter or digit",
+        ));
+    }
+    let dir = get_goal_dir(data_dir, &name);
+    if get_tasks_file(&dir).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("Goal '{}' already exists", name),
+        ));
+    }
+    fs::create_dir_all(&dir)?;
+    let task_list = TaskList {
+        tasks: vec![Task::new(
+            1,
+            "Define the first step",
+            "Press [e] to edit this task or [n] to add a new one.",
+        )],
+        current_index: 0,
+        the_goal: title.trim().to_string(),
+        target_date: None,
+        completion_log: BTreeMap::new(),
+    };
+    let _lock = lock_goal(&dir)?;
+    persist_tasks(&dir, &task_list)?;
+    Ok(name)
 }
 
 /// Loads tasks from the tasks file.
-pub fn load_tasks() -> io::Result<TaskList> {
-    let dir = get_tasks_dir();
+pub fn load_tasks(dir: &Path) -> io::Result<TaskList> {
     if !dir.exists() {
-        fs::create_dir_all(&dir)?;
+        fs::create_dir_all(dir)?;
     }
-    let path = get_tasks_file();
+    let path = get_tasks_file(dir);
     if !path.exists() {
-        return create_sample_tasks_file();
+        return create_sample_tasks_file(dir);
     }
     let content = fs::read_to_string(path)?;
-    let mut tasks_list: TaskList =
-        toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    parse_tasks(&content)
+}
+
+/// Loads tasks from the tasks file, failing if there is none.
+pub fn read_tasks(dir: &Path) -> io::Result<TaskList> {
+    match read_tasks_file(dir)? {
+        Some(content) => parse_tasks(&content),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No tasks file in {}", dir.display()),
+        )),
+    }
+}
+
+/// Error of [`parse_tasks`] when a tasks file is not valid TOML or lacks required fields.
+#[derive(Debug)]
+pub struct ParseError {
+    /// What is wrong, as reported by the TOML parser.
+    pub message: String,
+    /// The line and column of the error, both starting at 1, if known.
+    pub location: Option<(usize, usize)>,
+}
+
+impl ParseError {
+    /// Describes `error`, which occurred parsing `content`.
+    pub fn new(content: &str, error: toml::de::Error) -> ParseError {
+        let location = error.span().map(|span| {
+            let before = &content[..span.start.min(content.len())];
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+            (line, column)
+        });
+        ParseError {
+            message: error.message().to_string(),
+            location,
+        }
+    }
+
+    /// Returns the parse error behind `error`, if it is one.
+    pub fn find(error: &io::Error) -> Option<&ParseError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses the content of a tasks file, decrypting it first if it is encrypted.
+pub fn parse_tasks(content: &str) -> io::Result<TaskList> {
+    let content = crypto::decrypt(content)?;
+    let mut tasks_list: TaskList = toml::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, ParseError::new(&content, e)))?;
+
+    for task in &mut tasks_list.tasks {
+        task.extract_checklist();
+    }
 
     if tasks_list.current_index >= tasks_list.tasks.len() {
         tasks_list.current_index = tasks_list.tasks.iter().position(|t| !t.done).unwrap_or(0);
@@ -44,78 +221,99 @@ pub fn load_tasks() -> io::Result<TaskList> {
 }
 
 /// Creates a sample tasks file.
-fn create_sample_tasks_file() -> io::Result<TaskList> {
+fn create_sample_tasks_file(dir: &Path) -> io::Result<TaskList> {
     let sample_tasks = TaskList {
         tasks: vec![
-            Task {
-                id: 1,
-                title: "Make this task Done!".to_string(),
-                description: "- Press [d] to make this task Done".to_string(),
-                done: false,
-            },
-            Task {
-                id: 2,
-                title: "Add your desired tasks".to_string(),
-                description:
-                    "Open the $HOME/.tasks/tasks file and add as many sequential tasks you want."
-                        .to_string(),
-                done: false,
-            },
-            Task {
-                id: 3,
-                title: "Follow your dream!".to_string(),
-                description: "Don't think what I have to do today! just open Taskling and follow your plan.\n\nSee your progress visually."
-                    .to_string(),
-                done: false,
-            },
+            Task::new(1, "Make this task Done!", "- Press [d] to make this task Done"),
+            Task::new(
+                2,
+                "Add your desired tasks",
+                "Open the $HOME/.tasks/tasks file and add as many sequential tasks you want.",
+            ),
+            Task::new(
+                3,
+                "Follow your dream!",
+                "Don't think what I have to do today! just open Taskling and follow your plan.\n\nSee your progress visually.",
+            ),
         ],
         current_index: 0,
         the_goal: "1 Step at a time!".to_string(),
+        target_date: None,
+        completion_log: BTreeMap::new(),
     };
-    let toml = toml::to_string_pretty(&sample_tasks).map_err(io::Error::other)?;
-    fs::write(get_tasks_file(), toml)?;
+    let _lock = lock_goal(dir)?;
+    persist_tasks(dir, &sample_tasks)?;
     Ok(sample_tasks)
 }
 
-/// Persists tasks to the tasks file.
-pub fn persist_tasks(task_list: &TaskList) -> io::Result<()> {
-    let toml = toml::to_string_pretty(task_list).map_err(io::Error::other)?;
-    fs::write(get_tasks_file(), toml)
+/// An advisory lock on the task files of a goal, released when dropped.
+pub struct GoalLock {
+    _file: File,
 }
 
-/// Loads undone indexes from the undone indexes file.
-pub fn load_undone_indexes(tasks: &[Task]) -> io::Result<Vec<usize>> {
-    let path = get_undone_file();
-    if !path.exists() {
-        fs::write(&path, "")?;
-    }
-    if path.exists() {
-        let content = fs::read_to_string(path)?;
-        let mut indexes: Vec<usize> = content
-            .lines()
-            .filter_map(|line| line.parse::<usize>().ok())
-            .collect();
-
-        indexes.retain(|&i| i < tasks.len() && !tasks[i].done);
-        if indexes.is_empty() {
-            indexes = tasks
-                .iter()
-                .enumerate()
-                .filter_map(|(i, t)| if !t.done { Some(i) } else { None })
-                .collect();
+/// Locks the task files of the goal in `dir` against other tasklings
+/// processes, waiting for a process holding the lock to release it.
+pub fn lock_goal(dir: &Path) -> io::Result<GoalLock> {
+    fs::create_dir_all(dir)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(dir.join(LOCK_FILE))?;
+    file.lock()?;
+    Ok(GoalLock { _file: file })
+}
+
+/// Reads the raw content of the tasks file, or returns `None` if there is none.
+pub fn read_tasks_file(dir: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(get_tasks_file(dir)) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces the file at `path` with `content` through a temporary file, so that
+/// a crash never leaves a partially written file behind.
+pub(crate) fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(content.as_bytes())?;
+    file.as_file().sync_all()?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.as_file().set_permissions(metadata.permissions())?;
+    }
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Replaces the tasks file in `dir` with `content`, as is.
+pub fn write_tasks_file(dir: &Path, content: &str) -> io::Result<()> {
+    write_atomic(&get_tasks_file(dir), content)
+}
+
+/// Persists tasks to the tasks file, encrypted if encryption is enabled.
+pub fn persist_tasks(dir: &Path, task_list: &TaskList) -> io::Result<()> {
+    let toml = toml::to_string_pretty(task_list).map_err(io::Error::other)?;
+    let path = get_tasks_file(dir);
+    if crypto::is_enabled() {
+        // Encrypting yields new ciphertext every time; keep an unchanged file
+        // as is so that it doesn't show up as modified.
+        let unchanged = fs::read_to_string(&path).ok().is_some_and(|content| {
+            crypto::is_encrypted(&content) && crypto::decrypt(&content).is_ok_and(|old| old == toml)
+        });
+        if unchanged {
+            return Ok(());
         }
-        Ok(indexes)
-    } else {
-        Ok(tasks
-            .iter()
-            .enumerate()
-            .filter_map(|(i, t)| if !t.done { Some(i) } else { None })
-            .collect())
     }
+    write_atomic(&path, &crypto::encrypt(&toml)?)
 }
 
 /// Persists undone indexes to the undone indexes file.
-pub fn persist_undone_indexes(indexes: &[usize]) -> io::Result<()> {
+///
+/// The file is only written: the indexes are rebuilt from the tasks on load,
+/// since a merge or another program may have moved the tasks.
+pub fn persist_undone_indexes(dir: &Path, indexes: &[usize]) -> io::Result<()> {
     let content = indexes
         .iter()
         .map(|i| i.to_string())
@@ -124,5 +322,72 @@ pub fn persist_undone_indexes(indexes: &[usize]) -> io::Result<()> {
             "
 ",
         );
-    fs::write(get_undone_file(), content)
+    write_atomic(&get_undone_file(dir), &content)
+}
+
+/// Helpers for tests of the modules reading and writing goal directories.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// Writes a goal with an undone task per title to `dir`.
+    pub fn write_tasks(dir: &Path, titles: &[&str]) {
+        persist_tasks(dir, &TaskList::for_test(titles)).unwrap();
+    }
+
+    /// Applies `edit` to the tasks in `dir`.
+    pub fn edit_tasks(dir: &Path, edit: impl FnOnce(&mut TaskList)) {
+        let mut task_list = read_tasks(dir).unwrap();
+        edit(&mut task_list);
+        persist_tasks(dir, &task_list).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn goal_names_stay_inside_the_data_directory() {
+        assert!(validate_goal_name("work").is_ok());
+        assert!(validate_goal_name(".hidden").is_ok());
+        for name in ["", ".", "..", "a/b", "../work", "/work"] {
+            let error = validate_goal_name(name).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn missing_goals_are_not_created() {
+        let data_dir = TempDir::new().unwrap();
+        let error = existing_goal_dir(data_dir.path(), "work").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        let dir = get_goal_dir(data_dir.path(), "work");
+        assert_eq!(
+            read_tasks(&dir).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn deleted_active_goal_falls_back_to_an_existing_goal() {
+        let data_dir = TempDir::new().unwrap();
+        assert_eq!(load_active_goal(data_dir.path()), DEFAULT_GOAL);
+
+        let write_goal = |goal: &str| {
+            let dir = get_goal_dir(data_dir.path(), goal);
+            fs::create_dir_all(&dir).unwrap();
+            testing::write_tasks(&dir, &["Plan"]);
+        };
+        write_goal("work");
+        persist_active_goal(data_dir.path(), "gone").unwrap();
+        assert_eq!(load_active_goal(data_dir.path()), "work");
+
+        write_goal(DEFAULT_GOAL);
+        assert_eq!(load_active_goal(data_dir.path()), DEFAULT_GOAL);
+        persist_active_goal(data_dir.path(), "work").unwrap();
+        assert_eq!(load_active_goal(data_dir.path()), "work");
+    }
 }
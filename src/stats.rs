@@ -0,0 +1,170 @@
+//! Progress statistics.
+
+use crate::tasks::TaskManager;
+use chrono::{Datelike, Days, NaiveDate};
+use std::collections::BTreeMap;
+
+/// Number of days used to estimate the recent velocity.
+const VELOCITY_WINDOW_DAYS: u64 = 28;
+
+/// Number of weeks shown in the weekly completions chart.
+const WEEKS: u64 = 12;
+
+/// Number of days shown in the daily completions sparkline.
+const DAYS: u64 = 60;
+
+/// Maximum number of weeks shown in the completion heatmap.
+pub const HEATMAP_WEEKS: usize = 53;
+
+/// A week of the completion heatmap: the completions of each day from Monday
+/// to Sunday, or `None` for days in the future.
+pub type HeatmapWeek = [Option<(NaiveDate, u32)>; 7];
+
+/// Returns the completions of the last `weeks` weeks up to `today`, oldest first.
+pub fn heatmap(log: &BTreeMap<NaiveDate, u32>, today: NaiveDate, weeks: usize) -> Vec<HeatmapWeek> {
+    let this_week = today - Days::new(today.weekday().num_days_from_monday() as u64);
+    (0..weeks as u64)
+        .rev()
+        .map(|weeks_ago| {
+            let monday = this_week - Days::new(weeks_ago * 7);
+            let mut week = [None; 7];
+            for (offset, entry) in week.iter_mut().enumerate() {
+                let day = monday + Days::new(offset as u64);
+                if day <= today {
+                    *entry = Some((day, log.get(&day).copied().unwrap_or(0)));
+                }
+            }
+            week
+        })
+        .collect()
+}
+
+/// Statistics derived from the tasks of a goal.
+pub struct Stats {
+    /// Number of undone tasks at the end of each day, oldest first.
+    pub burndown: Vec<(NaiveDate, usize)>,
+    /// Number of completions per week, keyed by the Monday starting the week.
+    pub weekly_completions: Vec<(NaiveDate, u64)>,
+    /// Number of completions per day over the last days, oldest first.
+    pub daily_completions: Vec<u64>,
+    /// Average number of days between creating and finishing a task.
+    pub average_days_per_task: Option<f64>,
+    /// Completed tasks per day over the velocity window.
+    pub velocity: f64,
+    /// Number of undone tasks.
+    pub remaining: usize,
+    /// Date all tasks are expected to be done at the recent velocity.
+    pub projected_finish: Option<NaiveDate>,
+}
+
+impl Stats {
+    /// Computes the statistics of the tasks in `task_manager` as of `today`.
+    pub fn compute(task_manager: &TaskManager, today: NaiveDate) -> Stats {
+        let tasks = &task_manager.tasks;
+        let log = &task_manager.completion_log;
+
+        let start = tasks
+            .iter()
+            .flat_map(|t| [t.created_at, t.done_at])
+            .flatten()
+            .map(|time| time.date_naive())
+            .chain(log.keys().copied())
+            .min()
+            .unwrap_or(today)
+            .min(today);
+        let burndown = start
+            .iter_days()
+            .take_while(|day| *day <= today)
+            .map(|day| {
+                let undone = tasks
+                    .iter()
+                    .filter(|t| t.created_at.is_none_or(|c| c.date_naive() <= day))
+                    .filter(|t| !(t.done && t.done_at.is_none_or(|d| d.date_naive() <= day)))
+                    .count();
+                (day, undone)
+            })
+            .collect();
+
+        let this_week = today - Days::new(today.weekday().num_days_from_monday() as u64);
+        let weekly_completions = (0..WEEKS)
+            .rev()
+            .map(|weeks_ago| {
+                let week = this_week - Days::new(weeks_ago * 7);
+                let count = log
+                    .range(week..week + Days::new(7))
+                    .map(|(_, &count)| count as u64)
+                    .sum();
+                (week, count)
+            })
+            .collect();
+
+        let daily_completions = (0..DAYS)
+            .rev()
+            .map(|days_ago| {
+                let day = today - Days::new(days_ago);
+                log.get(&day).copied().unwrap_or(0) as u64
+            })
+            .collect();
+
+        let durations: Vec<f64> = tasks
+            .iter()
+            .filter_map(|t| Some((t.done_at? - t.created_at?).num_seconds() as f64 / 86_400.0))
+            .collect();
+        let average_days_per_task = if durations.is_empty() {
+            None
+        } else {
+            Some(durations.iter().sum::<f64>() / durations.len() as f64)
+        };
+
+        let window_start = today - Days::new(VELOCITY_WINDOW_DAYS - 1);
+        let recent: u32 = log.range(window_start..=today).map(|(_, &c)| c).sum();
+        let velocity = recent as f64 / VELOCITY_WINDOW_DAYS as f64;
+
+        let remaining = tasks.iter().filter(|t| !t.done).count();
+        let projected_finish = if remaining > 0 && velocity > 0.0 {
+            let days = (remaining as f64 / velocity).ceil() as u64;
+            today.checked_add_days(Days::new(days))
+        } else {
+            None
+        };
+
+        Stats {
+            burndown,
+            weekly_completions,
+            daily_completions,
+            average_days_per_task,
+            velocity,
+            remaining,
+            projected_finish,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::TaskList;
+    use tempfile::TempDir;
+
+    #[test]
+    fn stats_of_an_empty_list() {
+        let dir = TempDir::new().unwrap();
+        let task_list = TaskList::for_test(&[]);
+        let task_manager = TaskManager::new(task_list, dir.path().to_path_buf()).unwrap();
+        let today = NaiveDate::from_ymd_opt(2025, 3, 5).unwrap();
+
+        let stats = Stats::compute(&task_manager, today);
+
+        assert_eq!(stats.burndown, vec![(today, 0)]);
+        assert_eq!(stats.weekly_completions.len(), WEEKS as usize);
+        assert!(stats
+            .weekly_completions
+            .iter()
+            .all(|&(_, count)| count == 0));
+        assert_eq!(stats.daily_completions, vec![0; DAYS as usize]);
+        assert_eq!(stats.average_days_per_task, None);
+        assert_eq!(stats.velocity, 0.0);
+        assert_eq!(stats.remaining, 0);
+        assert_eq!(stats.projected_finish, None);
+    }
+}
@@ -0,0 +1,521 @@
+//! Synchronization of goals with a remote copy, in the background.
+
+use crate::config::{Backend, Config};
+use crate::folder::FolderStore;
+use crate::git::{GitBackend, RepoStatus};
+use crate::merge;
+use crate::persistence;
+use crate::webdav::WebDavStore;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+/// An error from syncing a goal.
+#[derive(Debug)]
+pub enum Error {
+    /// The remote could not be reached, or the task files could not be read or written.
+    Io(io::Error),
+    /// A git operation failed.
+    Git {
+        /// The equivalent git command line, e.g. `git push origin main`, or `git`
+        /// if the failing step has no such equivalent.
+        operation: String,
+        /// The error reported by libgit2.
+        error: git2::Error,
+    },
+    /// A server answered a request with an error status.
+    Http {
+        /// The method and URL of the request, e.g. `PUT https://example.com/tasks.toml`.
+        request: String,
+        /// The HTTP status code.
+        status: u16,
+        /// The body of the response.
+        output: String,
+    },
+}
+
+/// A result of a sync operation.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let output = match self {
+            Error::Io(e) => return write!(f, "{}", e),
+            Error::Git { operation, error } => {
+                return write!(f, "`{}` failed: {}", operation, error.message());
+            }
+            Error::Http {
+                request,
+                status,
+                output,
+            } => {
+                write!(f, "`{}` failed with status {}", request, status)?;
+                output
+            }
+        };
+        if !output.is_empty() {
+            write!(f, ": {}", output)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(error: git2::Error) -> Error {
+        Error::Git {
+            operation: "git".to_string(),
+            error,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
+
+/// A way of syncing goal directories with a remote copy.
+pub trait SyncBackend: Send + Sync {
+    /// Returns `true` if the goal in `dir` has a remote to sync with.
+    fn has_remote(&self, dir: &Path) -> Result<bool>;
+
+    /// Returns the label of the prompt asking for a remote URL when there is
+    /// none, or `None` if the remote can only be set in the configuration.
+    fn remote_prompt(&self) -> Option<&'static str>;
+
+    /// Sets the remote of the goal in `dir` to `url`.
+    fn add_remote(&self, dir: &Path, url: &str) -> Result<()>;
+
+    /// Describes the remote copy of the goal in `dir`, e.g. `origin/main`.
+    fn target(&self, dir: &Path) -> Result<String>;
+
+    /// Returns how the goal in `dir` compares to its remote, if the backend can
+    /// tell without contacting the remote.
+    fn status(&self, dir: &Path) -> Result<Option<RepoStatus>>;
+
+    /// Merges remote changes into the goal in `dir` and sends the result to the remote.
+    ///
+    /// `progress` is called with the name of each step as it starts.
+    fn push(&self, dir: &Path, progress: &dyn Fn(&'static str)) -> Result<()>;
+
+    /// Merges remote changes into the goal in `dir`.
+    ///
+    /// `progress` is called with the name of each step as it starts.
+    fn pull(&self, dir: &Path, progress: &dyn Fn(&'static str)) -> Result<()>;
+}
+
+/// Returns the sync backend selected in `config`.
+pub fn backend(config: &Config) -> Arc<dyn SyncBackend> {
+    match config.sync.backend {
+        Backend::Git => Arc::new(GitBackend::new(config.git.clone())),
+        Backend::Folder => Arc::new(FileBackend::new(FolderStore::new(
+            config.folder.path.clone(),
+        ))),
+        Backend::WebDav => Arc::new(FileBackend::new(WebDavStore::new(&config.webdav))),
+    }
+}
+
+/// A copy of a tasks file read from a remote store.
+pub struct RemoteFile {
+    /// The content of the file.
+    pub content: String,
+    /// An opaque version of the file, such as an HTTP entity tag, if the store has one.
+    pub version: Option<String>,
+}
+
+/// A place holding a copy of the tasks file of each goal, such as a shared folder.
+pub trait RemoteStore: Send + Sync {
+    /// Describes where the tasks file of `goal` is stored, or returns `None` if
+    /// the store is not configured.
+    fn location(&self, goal: &str) -> Option<String>;
+
+    /// Reads the tasks file of `goal`, or returns `None` if there is none yet.
+    fn read(&self, goal: &str) -> Result<Option<RemoteFile>>;
+
+    /// Writes the tasks file of `goal`.
+    ///
+    /// If the store supports versions, the write fails when the file is no
+    /// longer at `version`, or already exists when `version` is `None`.
+    fn write(&self, goal: &str, content: &str, version: Option<&str>) -> Result<()>;
+}
+
+/// Name of the file remembering the tasks as they were after the last sync,
+/// used as the base of three-way merges by [`FileBackend`].
+const SYNC_BASE_FILE: &str = ".sync_base.toml";
+
+/// Syncs the tasks file of each goal with a copy in a [`RemoteStore`].
+///
+/// Without a history to consult, the tasks as they were after the last sync
+/// are kept next to the tasks file and used as the base when merging.
+pub struct FileBackend<S> {
+    store: S,
+}
+
+impl<S: RemoteStore> FileBackend<S> {
+    /// Creates a backend syncing with `store`.
+    pub fn new(store: S) -> FileBackend<S> {
+        FileBackend { store }
+    }
+
+    /// Merges `theirs` into the tasks in `dir` and returns the resulting tasks file.
+    ///
+    /// The goal is locked while the tasks file is merged and written.
+    fn merge(&self, dir: &Path, theirs: &str) -> Result<String> {
+        let _lock = persistence::lock_goal(dir)?;
+        let path = dir.join(persistence::TASKS_FILE);
+        let Some(ours) = read_optional(&path)? else {
+            // Nothing local yet: adopt the remote tasks as they are.
+            persistence::write_tasks_file(dir, theirs)?;
+            return Ok(theirs.to_string());
+        };
+        let base = read_optional(&dir.join(SYNC_BASE_FILE))?;
+        if base.as_deref() != Some(theirs) {
+            let base = base
+                .map(|base| persistence::parse_tasks(&base))
+                .transpose()?;
+            let ours = persistence::parse_tasks(&ours)?;
+            let theirs = persistence::parse_tasks(theirs)?;
+            let merged = merge::merge(base.as_ref(), &ours, &theirs);
+            persistence::persist_tasks(dir, &merged)?;
+        }
+        Ok(fs::read_to_string(path)?)
+    }
+}
+
+impl<S: RemoteStore> SyncBackend for FileBackend<S> {
+    fn has_remote(&self, dir: &Path) -> Result<bool> {
+        Ok(self.store.location(&goal_name(dir)).is_some())
+    }
+
+    fn remote_prompt(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn add_remote(&self, _dir: &Path, _url: &str) -> Result<()> {
+        Err(not_configured())
+    }
+
+    fn target(&self, dir: &Path) -> Result<String> {
+        self.store
+            .location(&goal_name(dir))
+            .ok_or_else(not_configured)
+    }
+
+    fn status(&self, _dir: &Path) -> Result<Option<RepoStatus>> {
+        Ok(None)
+    }
+
+    fn push(&self, dir: &Path, progress: &dyn Fn(&'static str)) -> Result<()> {
+        let goal = goal_name(dir);
+        progress("Downloading");
+        let remote = self.store.read(&goal)?;
+        let local = match &remote {
+            Some(remote) => {
+                progress("Merging");
+                self.merge(dir, &remote.content)?
+            }
+            None => read_optional(&dir.join(persistence::TASKS_FILE))?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No tasks to push in {}", dir.display()),
+                )
+            })?,
+        };
+        if remote.as_ref().map(|remote| &remote.content) != Some(&local) {
+            progress("Uploading");
+            let version = remote.as_ref().and_then(|remote| remote.version.as_deref());
+            self.store.write(&goal, &local, version)?;
+        }
+        fs::write(dir.join(SYNC_BASE_FILE), local)?;
+        Ok(())
+    }
+
+    fn pull(&self, dir: &Path, progress: &dyn Fn(&'static str)) -> Result<()> {
+        progress("Downloading");
+        let Some(remote) = self.store.read(&goal_name(dir))? else {
+            return Ok(());
+        };
+        progress("Merging");
+        fs::create_dir_all(dir)?;
+        self.merge(dir, &remote.content)?;
+        fs::write(dir.join(SYNC_BASE_FILE), remote.content)?;
+        Ok(())
+    }
+}
+
+/// Returns the name of the goal stored in `dir`.
+fn goal_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| persistence::DEFAULT_GOAL.to_string())
+}
+
+/// Reads the file at `path`, or returns `None` if it doesn't exist.
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn not_configured() -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::NotFound,
+        "No sync location configured; set it in the configuration file",
+    ))
+}
+
+/// A sync operation of a goal with its remote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Send local changes to the remote.
+    Push,
+    /// Merge remote changes into the local tasks.
+    Pull,
+}
+
+impl SyncAction {
+    /// Returns the verb shown in messages, e.g. "Push".
+    pub fn name(self) -> &'static str {
+        match self {
+            SyncAction::Push => "Push",
+            SyncAction::Pull => "Pull",
+        }
+    }
+}
+
+/// A message from the worker thread.
+enum SyncEvent {
+    /// A new step started, e.g. "Fetching".
+    Progress(&'static str),
+    /// The operation finished.
+    Finished(Result<()>),
+}
+
+/// A sync operation running on a worker thread.
+pub struct SyncWorker {
+    /// The operation being run.
+    pub action: SyncAction,
+    /// The step currently running.
+    pub step: &'static str,
+    /// When the operation started.
+    pub started_at: Instant,
+    events: Receiver<SyncEvent>,
+}
+
+impl SyncWorker {
+    /// Starts `action` on the goal in `dir` on a new thread.
+    pub fn spawn(action: SyncAction, dir: PathBuf, backend: Arc<dyn SyncBackend>) -> SyncWorker {
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || {
+            let progress = |step| {
+                let _ = sender.send(SyncEvent::Progress(step));
+            };
+            let result = match action {
+                SyncAction::Push => backend.push(&dir, &progress),
+                SyncAction::Pull => backend.pull(&dir, &progress),
+            };
+            let _ = sender.send(SyncEvent::Finished(result));
+        });
+        SyncWorker {
+            action,
+            step: "Starting",
+            started_at: Instant::now(),
+            events,
+        }
+    }
+
+    /// Handles progress reported so far and returns the result once the operation finished.
+    pub fn poll(&mut self) -> Option<Result<()>> {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => {
+                    if let Some(result) = self.handle(event) {
+                        return Some(result);
+                    }
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(Err(worker_died())),
+            }
+        }
+    }
+
+    /// Blocks until the operation finished and returns its result.
+    pub fn wait(&mut self) -> Result<()> {
+        loop {
+            match self.events.recv() {
+                Ok(event) => {
+                    if let Some(result) = self.handle(event) {
+                        return result;
+                    }
+                }
+                Err(_) => return Err(worker_died()),
+            }
+        }
+    }
+
+    fn handle(&mut self, event: SyncEvent) -> Option<Result<()>> {
+        match event {
+            SyncEvent::Progress(step) => {
+                self.step = step;
+                None
+            }
+            SyncEvent::Finished(result) => Some(result),
+        }
+    }
+}
+
+fn worker_died() -> Error {
+    Error::Io(io::Error::other("sync thread stopped unexpectedly"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::testing::{edit_tasks, write_tasks};
+    use crate::tasks::{Task, TaskList};
+    use tempfile::TempDir;
+
+    /// Two machines with the goal `work`, syncing through a shared folder.
+    struct Folders {
+        _root: TempDir,
+        shared: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+    }
+
+    impl Folders {
+        fn new() -> Folders {
+            let root = TempDir::new().unwrap();
+            let [shared, ours, theirs] = ["shared", "ours/work", "theirs/work"].map(|dir| {
+                let path = root.path().join(dir);
+                fs::create_dir_all(&path).unwrap();
+                path
+            });
+            Folders {
+                _root: root,
+                shared,
+                ours,
+                theirs,
+            }
+        }
+
+        fn backend(&self) -> FileBackend<FolderStore> {
+            FileBackend::new(FolderStore::new(Some(self.shared.clone())))
+        }
+
+        fn shared_tasks(&self) -> TaskList {
+            persistence::read_tasks(&self.shared.join("work")).unwrap()
+        }
+    }
+
+    #[test]
+    fn first_push_uploads_the_local_tasks() {
+        let folders = Folders::new();
+        write_tasks(&folders.ours, &["Plan", "Build"]);
+
+        folders.backend().push(&folders.ours, &|_| {}).unwrap();
+
+        let local = fs::read_to_string(folders.ours.join(persistence::TASKS_FILE)).unwrap();
+        let shared = fs::read_to_string(folders.shared.join("work/tasks.toml")).unwrap();
+        assert_eq!(shared, local);
+        let base = fs::read_to_string(folders.ours.join(SYNC_BASE_FILE)).unwrap();
+        assert_eq!(base, local);
+    }
+
+    #[test]
+    fn push_without_tasks_fails() {
+        let folders = Folders::new();
+
+        let error = folders.backend().push(&folders.ours, &|_| {}).unwrap_err();
+
+        assert!(matches!(error, Error::Io(e) if e.kind() == io::ErrorKind::NotFound));
+        assert!(!folders.ours.join(persistence::TASKS_FILE).exists());
+        assert!(!folders.shared.join("work").exists());
+    }
+
+    #[test]
+    fn pull_merges_remote_tasks() {
+        let folders = Folders::new();
+        let backend = folders.backend();
+        write_tasks(&folders.ours, &["Plan", "Build"]);
+        backend.push(&folders.ours, &|_| {}).unwrap();
+
+        backend.pull(&folders.theirs, &|_| {}).unwrap();
+        edit_tasks(&folders.theirs, |tasks| {
+            tasks.tasks.push(Task::new(3, "Ship", ""))
+        });
+        backend.push(&folders.theirs, &|_| {}).unwrap();
+        backend.pull(&folders.ours, &|_| {}).unwrap();
+
+        let ours = persistence::read_tasks(&folders.ours).unwrap();
+        assert_eq!(ours.titles(), ["Plan", "Build", "Ship"]);
+    }
+
+    #[test]
+    fn concurrent_edits_are_merged_against_the_last_sync() {
+        let folders = Folders::new();
+        let backend = folders.backend();
+        write_tasks(&folders.ours, &["Plan", "Build"]);
+        backend.push(&folders.ours, &|_| {}).unwrap();
+        backend.pull(&folders.theirs, &|_| {}).unwrap();
+
+        edit_tasks(&folders.ours, |tasks| {
+            tasks.tasks[0].title = "Plan it".to_string()
+        });
+        edit_tasks(&folders.theirs, |tasks| tasks.tasks[1].done = true);
+        backend.push(&folders.ours, &|_| {}).unwrap();
+        backend.push(&folders.theirs, &|_| {}).unwrap();
+        backend.pull(&folders.ours, &|_| {}).unwrap();
+
+        for tasks in [
+            folders.shared_tasks(),
+            persistence::read_tasks(&folders.ours).unwrap(),
+            persistence::read_tasks(&folders.theirs).unwrap(),
+        ] {
+            assert_eq!(tasks.titles(), ["Plan it", "Build"]);
+            assert!(!tasks.tasks[0].done);
+            assert!(tasks.tasks[1].done);
+        }
+    }
+
+    #[test]
+    fn pull_waits_for_the_goal_lock() {
+        let folders = Folders::new();
+        write_tasks(&folders.ours, &["Plan"]);
+        folders.backend().push(&folders.ours, &|_| {}).unwrap();
+        write_tasks(&folders.theirs, &["Local"]);
+        let lock = persistence::lock_goal(&folders.theirs).unwrap();
+
+        let (sender, finished) = mpsc::channel();
+        let (backend, dir) = (folders.backend(), folders.theirs.clone());
+        thread::spawn(move || sender.send(backend.pull(&dir, &|_| {}).is_ok()));
+        let timeout = std::time::Duration::from_millis(200);
+        assert!(finished.recv_timeout(timeout).is_err());
+        drop(lock);
+
+        assert!(finished.recv().unwrap());
+        assert_eq!(
+            persistence::read_tasks(&folders.theirs).unwrap().titles(),
+            ["Plan", "Local"]
+        );
+    }
+}
@@ -1,8 +1,13 @@
 //! Task management.
 
 use crate::persistence;
+use chrono::{DateTime, Days, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::io;
+use std::path::PathBuf;
+use std::time::Instant;
 
 /// A single task.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -11,6 +16,106 @@ pub struct Task {
     pub title: String,
     pub description: String,
     pub done: bool,
+    /// Ids of tasks that must be done before this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<i32>,
+    /// Date by which the task should be done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
+    /// Date before which the task is not started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_after: Option<NaiveDate>,
+    /// When the task was created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Local>>,
+    /// When the task was last marked done.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub done_at: Option<DateTime<Local>>,
+    /// When the task was last reopened.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reopened_at: Option<DateTime<Local>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checklist: Vec<ChecklistItem>,
+}
+
+/// A checklist item inside a task.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChecklistItem {
+    pub title: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+impl Task {
+    /// Creates a new undone task created now.
+    pub fn new(id: i32, title: impl Into<String>, description: impl Into<String>) -> Task {
+        Task {
+            id,
+            title: title.into(),
+            description: description.into(),
+            done: false,
+            depends_on: Vec::new(),
+            due: None,
+            start_after: None,
+            created_at: Some(Local::now()),
+            done_at: None,
+            reopened_at: None,
+            checklist: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if the task is not done and its due date has passed.
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        !self.done && self.due.is_some_and(|due| due < today)
+    }
+
+    /// Returns `true` if the task's start date has been reached.
+    pub fn is_started(&self, today: NaiveDate) -> bool {
+        self.start_after.is_none_or(|start| start <= today)
+    }
+
+    /// Returns the completed fraction of the task, counting checklist items partially.
+    pub fn progress(&self) -> f64 {
+        if self.done {
+            1.0
+        } else if self.checklist.is_empty() {
+            0.0
+        } else {
+            let done = self.checklist.iter().filter(|i| i.done).count();
+            done as f64 / self.checklist.len() as f64
+        }
+    }
+
+    /// Moves `- [ ]` and `- [x]` lines from the description into the checklist.
+    pub fn extract_checklist(&mut self) {
+        if !self
+            .description
+            .lines()
+            .any(|line| is_checklist_line(line.trim_start()))
+        {
+            return;
+        }
+        let mut description = Vec::new();
+        for line in self.description.lines() {
+            let trimmed = line.trim_start();
+            let item = if let Some(title) = trimmed.strip_prefix("- [ ] ") {
+                Some((title, false))
+            } else {
+                trimmed
+                    .strip_prefix("- [x] ")
+                    .or_else(|| trimmed.strip_prefix("- [X] "))
+                    .map(|title| (title, true))
+            };
+            match item {
+                Some((title, done)) => self.checklist.push(ChecklistItem {
+                    title: title.trim().to_string(),
+                    done,
+                }),
+                None => description.push(line),
+            }
+        }
+        self.description = description.join("\n").trim_end().to_string();
+    }
 }
 
 /// A list of tasks.
@@ -21,6 +126,46 @@ pub struct TaskList {
     pub current_index: usize,
     #[serde(default = "default_goal")]
     pub the_goal: String,
+    /// Deadline of the goal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_date: Option<NaiveDate>,
+    /// Number of tasks completed per day.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub completion_log: BTreeMap<NaiveDate, u32>,
+}
+
+#[cfg(test)]
+impl TaskList {
+    /// Returns the goal "Goal" with an undone task per title, numbered from 1.
+    pub fn for_test(titles: &[&str]) -> TaskList {
+        TaskList {
+            tasks: (1..)
+                .zip(titles)
+                .map(|(id, title)| Task::new(id, *title, ""))
+                .collect(),
+            current_index: 0,
+            the_goal: "Goal".to_string(),
+            target_date: None,
+            completion_log: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the titles of the tasks, in order.
+    pub fn titles(&self) -> Vec<&str> {
+        self.tasks.iter().map(|task| task.title.as_str()).collect()
+    }
+}
+
+/// Returns `true` if `line` is a Markdown checklist item.
+fn is_checklist_line(line: &str) -> bool {
+    ["- [ ] ", "- [x] ", "- [X] "]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+/// Returns the indexes of the undone tasks in `tasks`.
+fn undone_indexes(tasks: &[Task]) -> Vec<usize> {
+    (0..tasks.len()).filter(|&i| !tasks[i].done).collect()
 }
 
 fn default_goal() -> String {
@@ -35,12 +180,73 @@ pub struct TaskManager {
     pub undone_indexes: Vec<usize>,
     pub undone_pos: usize,
     pub the_goal: String,
+    pub target_date: Option<NaiveDate>,
+    pub completion_log: BTreeMap<NaiveDate, u32>,
+    /// Directory of the goal the tasks belong to.
+    pub dir: PathBuf,
+    /// If true, changes are kept in memory until the next explicit [`TaskManager::save`],
+    /// e.g. while a sync is writing the task files.
+    pub defer_saves: bool,
+    /// When the tasks were last changed, if they changed since they were loaded.
+    pub changed_at: Option<Instant>,
+    /// If true, the last save was refused because another process changed the
+    /// tasks file; see [`TaskManager::save`].
+    pub conflict: bool,
+    /// If true, there are changes that are not written to the tasks file yet.
+    pub unsaved: bool,
+    /// The tasks file as it was when last loaded or saved, to notice changes
+    /// made by other processes.
+    disk_content: Option<String>,
+}
+
+/// Error of [`TaskManager::save`] when the tasks file changed on disk since it was loaded.
+#[derive(Debug)]
+pub struct ChangedOnDisk;
+
+impl fmt::Display for ChangedOnDisk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} was changed by another program since it was loaded",
+            persistence::TASKS_FILE
+        )
+    }
+}
+
+impl std::error::Error for ChangedOnDisk {}
+
+/// Error of [`TaskManager::mark_done`] when the task has open prerequisites.
+#[derive(Debug)]
+pub struct Blocked {
+    /// Id of the task.
+    pub id: i32,
+    /// Ids of its open prerequisites.
+    pub blockers: Vec<i32>,
 }
 
+impl Blocked {
+    /// Returns the blocked task behind `error`, if it is one.
+    pub fn find(error: &io::Error) -> Option<&Blocked> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for Blocked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids: Vec<String> = self.blockers.iter().map(|id| format!("#{}", id)).collect();
+        write!(f, "Task #{} is blocked by {}", self.id, ids.join(", "))
+    }
+}
+
+impl std::error::Error for Blocked {}
+
 impl TaskManager {
-    /// Creates a new `TaskManager`.
-    pub fn new(task_list: TaskList) -> io::Result<Self> {
-        let undone_indexes = persistence::load_undone_indexes(&task_list.tasks)?;
+    /// Creates a new `TaskManager` for the goal stored in `dir`.
+    ///
+    /// The tasks file currently in `dir` is taken as the version `task_list` was loaded from.
+    pub fn new(task_list: TaskList, dir: PathBuf) -> io::Result<Self> {
+        let disk_content = persistence::read_tasks_file(&dir)?;
+        let undone_indexes = undone_indexes(&task_list.tasks);
         let undone_pos = task_list.tasks.iter().position(|t| !t.done).unwrap_or(0);
         let current_index = undone_indexes.get(undone_pos).copied().unwrap_or(0);
         Ok(Self {
@@ -49,6 +255,14 @@ impl TaskManager {
             undone_indexes,
             undone_pos,
             the_goal: task_list.the_goal,
+            target_date: task_list.target_date,
+            completion_log: task_list.completion_log,
+            dir,
+            defer_saves: false,
+            changed_at: None,
+            conflict: false,
+            unsaved: false,
+            disk_content,
         })
     }
 
@@ -57,12 +271,30 @@ impl TaskManager {
         &self.tasks[self.current_index]
     }
 
+    /// Returns the id for a new task.
+    pub fn next_id(&self) -> i32 {
+        self.tasks.iter().map(|t| t.id).max().unwrap_or(0) + 1
+    }
+
+    /// Moves to the task with the given id.
+    ///
+    /// Returns `false` if there is no such task.
+    pub fn select(&mut self, id: i32) -> bool {
+        match self.tasks.iter().position(|t| t.id == id) {
+            Some(index) => {
+                self.current_index = index;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Returns a mutable reference to the current task.
     pub fn current_task_mut(&mut self) -> &mut Task {
         &mut self.tasks[self.current_index]
     }
 
-    /// Moves to the next undone task.
+    /// Moves to the next undone task that can be worked on.
     pub fn next_undone(&mut self) {
         if self.is_done() {
             return;
@@ -72,20 +304,54 @@ impl TaskManager {
             .undone_indexes
             .iter()
             .enumerate()
-            .find(|&(_, i)| i > &self.current_index)
+            .find(|&(_, &i)| i > self.current_index && self.is_actionable(i))
         {
             self.undone_pos = pos;
             self.current_index = idx;
         }
     }
 
-    /// Moves to the first undone task.
+    /// Moves to the first undone task that can be worked on.
+    ///
+    /// Falls back to the first undone task if no undone task can be worked on.
     pub fn first_undone(&mut self) {
         if self.is_done() {
             return;
         }
-        self.undone_pos = 0;
-        self.current_index = self.undone_indexes[0];
+        self.undone_pos = self
+            .undone_indexes
+            .iter()
+            .position(|&i| self.is_actionable(i))
+            .unwrap_or(0);
+        self.current_index = self.undone_indexes[self.undone_pos];
+    }
+
+    /// Returns the open prerequisites of the task at `index`.
+    pub fn blockers(&self, index: usize) -> Vec<&Task> {
+        self.tasks[index]
+            .depends_on
+            .iter()
+            .filter_map(|id| self.tasks.iter().find(|t| t.id == *id))
+            .filter(|t| !t.done)
+            .collect()
+    }
+
+    /// Returns `true` if the task at `index` has open prerequisites.
+    pub fn is_blocked(&self, index: usize) -> bool {
+        !self.blockers(index).is_empty()
+    }
+
+    /// Returns `true` if the task at `index` is started and not blocked.
+    pub fn is_actionable(&self, index: usize) -> bool {
+        self.tasks[index].is_started(Local::now().date_naive()) && !self.is_blocked(index)
+    }
+
+    /// Returns the number of days left until the goal's target date.
+    ///
+    /// The result is negative once the target date has passed.
+    pub fn days_remaining(&self) -> Option<i64> {
+        self.target_date
+            .map(|target| (target - Local::now().date_naive()).num_days())
     }
 
     /// Moves to the last task.
@@ -108,20 +374,33 @@ impl TaskManager {
     }
 
     /// Marks the current task as done.
-    pub fn mark_done(&mut self) {
-        self.current_task_mut().done = true;
-        self.undone_indexes.remove(self.undone_pos);
+    ///
+    /// Refuses with [`Blocked`] if the task has open prerequisites.
+    pub fn mark_done(&mut self) -> io::Result<()> {
+        let index = self.current_index;
+        let blockers: Vec<i32> = self.blockers(index).iter().map(|t| t.id).collect();
+        if !blockers.is_empty() {
+            return Err(io::Error::other(Blocked {
+                id: self.tasks[index].id,
+                blockers,
+            }));
+        }
+        self.record_done(index);
+        if let Some(pos) = self.undone_indexes.iter().position(|&i| i == index) {
+            self.undone_indexes.remove(pos);
+            self.undone_pos = pos;
+        }
 
         if self.undone_pos >= self.undone_indexes.len() && !self.undone_indexes.is_empty() {
             self.undone_pos = self.undone_indexes.len() - 1;
         }
-        self.persist();
+        self.persist()
     }
 
     /// Marks the current task as not done.
-    pub fn mark_undone(&mut self) {
+    pub fn mark_undone(&mut self) -> io::Result<()> {
         let index = self.current_index;
-        self.tasks[index].done = false;
+        self.record_reopened(index);
 
         match self.undone_indexes.binary_search(&index) {
             Ok(_) => {} // already exists
@@ -133,22 +412,171 @@ impl TaskManager {
             .position(|&i| i == index)
             .unwrap();
 
-        self.persist();
+        self.persist()
+    }
+
+    /// Toggles a checklist item of the current task.
+    ///
+    /// The task is marked done once all of its items are done and it is not
+    /// blocked, and reopened when an item of a done task is unchecked.
+    pub fn toggle_checklist_item(&mut self, item: usize) -> io::Result<()> {
+        let task = self.current_task_mut();
+        let Some(entry) = task.checklist.get_mut(item) else {
+            return Ok(());
+        };
+        entry.done = !entry.done;
+        let all_done = task.checklist.iter().all(|i| i.done);
+        let done = task.done;
+        if all_done && !done && !self.is_blocked(self.current_index) {
+            self.mark_done()
+        } else if !all_done && done {
+            self.mark_undone()
+        } else {
+            self.persist()
+        }
+    }
+
+    /// Returns the completed fraction of all tasks, between 0 and 1.
+    pub fn progress(&self) -> f64 {
+        if self.tasks.is_empty() {
+            return 0.0;
+        }
+        self.tasks.iter().map(Task::progress).sum::<f64>() / self.tasks.len() as f64
+    }
+
+    /// Returns the current and the longest daily completion streak in days.
+    ///
+    /// The current streak is still alive if nothing has been completed today yet.
+    pub fn streaks(&self, today: NaiveDate) -> (u32, u32) {
+        let mut longest = 0;
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+        for (&day, _) in self.completion_log.iter().filter(|(_, &count)| count > 0) {
+            run = match previous {
+                Some(prev) if prev.checked_add_days(Days::new(1)) == Some(day) => run + 1,
+                _ => 1,
+            };
+            longest = longest.max(run);
+            previous = Some(day);
+        }
+        let yesterday = today.checked_sub_days(Days::new(1));
+        let current = match previous {
+            Some(last) if last == today || Some(last) == yesterday => run,
+            _ => 0,
+        };
+        (current, longest)
     }
 
     /// Adds a new task.
-    pub fn add_task(&mut self, task: Task) {
+    pub fn add_task(&mut self, mut task: Task) -> io::Result<()> {
+        task.created_at.get_or_insert_with(Local::now);
+        let done = task.done;
         self.tasks.push(task);
-        self.undone_indexes.push(self.tasks.len() - 1);
-        self.persist();
+        if !done {
+            self.undone_indexes.push(self.tasks.len() - 1);
+        }
+        self.persist()
+    }
+
+    /// Checks that `task`, a new or edited task, can be saved into the list.
+    ///
+    /// Returns why not if the task depends on itself, directly or through its
+    /// prerequisites, or is marked done while one of its prerequisites is open.
+    pub fn check_task(&self, task: &Task) -> Result<(), String> {
+        let prerequisites = |id: i32| {
+            if id == task.id {
+                return task.depends_on.as_slice();
+            }
+            self.tasks
+                .iter()
+                .find(|t| t.id == id)
+                .map_or(&[][..], |t| t.depends_on.as_slice())
+        };
+        let mut seen = BTreeSet::new();
+        let mut pending = task.depends_on.clone();
+        while let Some(id) = pending.pop() {
+            if id == task.id {
+                return Err(format!("Task #{} depends on itself", task.id));
+            }
+            if seen.insert(id) {
+                pending.extend(prerequisites(id));
+            }
+        }
+
+        let was_done = self.tasks.iter().any(|t| t.id == task.id && t.done);
+        if task.done && !was_done {
+            let open: Vec<String> = task
+                .depends_on
+                .iter()
+                .filter(|&&id| self.tasks.iter().any(|t| t.id == id && !t.done))
+                .map(|id| format!("#{}", id))
+                .collect();
+            if !open.is_empty() {
+                return Err(format!(
+                    "Task #{} can't be done while it is blocked by {}",
+                    task.id,
+                    open.join(", ")
+                ));
+            }
+        }
+        Ok(())
     }
 
     /// Updates an existing task.
-    pub fn update_task(&mut self, task: Task) {
-        if let Some(t) = self.tasks.iter_mut().find(|t| t.id == task.id) {
-            *t = task;
+    ///
+    /// The task is expected to have passed [`TaskManager::check_task`].
+    pub fn update_task(&mut self, task: Task) -> io::Result<()> {
+        if let Some(index) = self.tasks.iter().position(|t| t.id == task.id) {
+            let was_done = self.tasks[index].done;
+            let done = task.done;
+            self.tasks[index] = task;
+            if done && !was_done {
+                self.tasks[index].done = false;
+                self.record_done(index);
+                self.undone_indexes.retain(|&i| i != index);
+            } else if !done && was_done {
+                self.tasks[index].done = true;
+                self.record_reopened(index);
+                if let Err(pos) = self.undone_indexes.binary_search(&index) {
+                    self.undone_indexes.insert(pos, index);
+                }
+            }
+            if self.undone_pos >= self.undone_indexes.len() {
+                self.undone_pos = self.undone_indexes.len().saturating_sub(1);
+            }
+        }
+        self.persist()
+    }
+
+    /// Marks the task at `index` as done and logs the completion.
+    fn record_done(&mut self, index: usize) {
+        let task = &mut self.tasks[index];
+        if task.done {
+            return;
+        }
+        let now = Local::now();
+        task.done = true;
+        task.done_at = Some(now);
+        *self.completion_log.entry(now.date_naive()).or_insert(0) += 1;
+    }
+
+    /// Marks the task at `index` as not done and takes back its logged completion.
+    fn record_reopened(&mut self, index: usize) {
+        let task = &mut self.tasks[index];
+        if !task.done {
+            return;
+        }
+        task.done = false;
+        task.reopened_at = Some(Local::now());
+        if let Some(done_at) = task.done_at.take() {
+            let day = done_at.date_naive();
+            if let Some(count) = self.completion_log.get_mut(&day) {
+                *count = count.saturating_sub(1);
+                if *count == 0 {
+                    self.completion_log.remove(&day);
+                }
+            }
         }
-        self.persist();
     }
 
     /// Returns `true` if all tasks are done.
@@ -156,23 +584,273 @@ impl TaskManager {
         self.undone_indexes.is_empty()
     }
 
-    /// Persists the task state to disk.
-    fn persist(&self) {
+    /// Records a change and persists the task state to disk, unless saves are deferred.
+    fn persist(&mut self) -> io::Result<()> {
+        self.changed_at = Some(Instant::now());
+        self.unsaved = true;
+        if self.defer_saves {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    /// Returns the task state as it is stored on disk.
+    pub fn task_list(&self) -> TaskList {
         let current_index = self
             .undone_indexes
             .get(self.undone_pos)
             .copied()
             .unwrap_or(0);
-        let task_list = TaskList {
+        TaskList {
             tasks: self.tasks.clone(),
             current_index,
             the_goal: self.the_goal.clone(),
-        };
-        if let Err(e) = persistence::persist_tasks(&task_list) {
-            eprintln!("Failed to persist tasks: {}", e);
+            target_date: self.target_date,
+            completion_log: self.completion_log.clone(),
         }
-        if let Err(e) = persistence::persist_undone_indexes(&self.undone_indexes) {
-            eprintln!("Failed to persist undone indexes: {}", e);
+    }
+
+    /// Replaces all tasks with `task_list`, e.g. an earlier version, and moves to
+    /// the first undone task.
+    ///
+    /// The tasks file stays the version the next [`TaskManager::save`] checks against.
+    pub fn replace(&mut self, task_list: TaskList) {
+        self.tasks = task_list.tasks;
+        self.the_goal = task_list.the_goal;
+        self.target_date = task_list.target_date;
+        self.completion_log = task_list.completion_log;
+        self.undone_indexes = undone_indexes(&self.tasks);
+        self.current_index = 0;
+        self.undone_pos = 0;
+        self.first_undone();
+        self.changed_at = Some(Instant::now());
+        self.unsaved = true;
+    }
+
+    /// Persists the task state to disk.
+    ///
+    /// The goal is locked while the tasks file is checked and written. If another
+    /// process changed the file since it was loaded, nothing is written, `conflict`
+    /// is set and [`ChangedOnDisk`] is returned.
+    pub fn save(&mut self) -> io::Result<()> {
+        let _lock = persistence::lock_goal(&self.dir)?;
+        if persistence::read_tasks_file(&self.dir)? != self.disk_content {
+            self.conflict = true;
+            return Err(io::Error::other(ChangedOnDisk));
         }
+        persistence::persist_tasks(&self.dir, &self.task_list())?;
+        persistence::persist_undone_indexes(&self.dir, &self.undone_indexes)?;
+        self.disk_content = persistence::read_tasks_file(&self.dir)?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Returns `true` if the tasks file changed on disk since it was last loaded or saved.
+    pub fn changed_on_disk(&self) -> bool {
+        persistence::read_tasks_file(&self.dir).is_ok_and(|content| content != self.disk_content)
+    }
+
+    /// Returns the tasks as they were when last loaded or saved.
+    pub fn disk_tasks(&self) -> Option<TaskList> {
+        self.disk_content
+            .as_deref()
+            .and_then(|content| persistence::parse_tasks(content).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, day).unwrap()
+    }
+
+    /// Returns a manager of `task_list`, stored in a temporary directory.
+    fn manager_of(task_list: TaskList) -> (TempDir, TaskManager) {
+        let dir = TempDir::new().unwrap();
+        let task_manager = TaskManager::new(task_list, dir.path().to_path_buf()).unwrap();
+        (dir, task_manager)
+    }
+
+    /// Returns a manager of a goal with one completion on each of `days`.
+    fn manager(days: &[u32]) -> (TempDir, TaskManager) {
+        let mut task_list = TaskList::for_test(&["Task"]);
+        task_list.completion_log = days.iter().map(|&d| (day(d), 1)).collect();
+        manager_of(task_list)
+    }
+
+    /// Returns a goal whose first task has the checklist "One" and "Two" with "Two" done.
+    fn checklist_goal(titles: &[&str]) -> TaskList {
+        let mut task_list = TaskList::for_test(titles);
+        task_list.tasks[0].description = "Steps:\n- [ ] One\n  - [x] Two".to_string();
+        task_list.tasks[0].extract_checklist();
+        task_list
+    }
+
+    #[test]
+    fn streak_is_broken_by_a_gap_day() {
+        let (_dir, task_manager) = manager(&[1, 2, 3, 5, 6]);
+        assert_eq!(task_manager.streaks(day(6)), (2, 3));
+    }
+
+    #[test]
+    fn streak_survives_until_the_end_of_the_next_day() {
+        let (_dir, task_manager) = manager(&[1, 2]);
+        assert_eq!(task_manager.streaks(day(3)), (2, 2));
+        assert_eq!(task_manager.streaks(day(4)), (0, 2));
+    }
+
+    #[test]
+    fn days_without_completions_do_not_count() {
+        let (_dir, mut task_manager) = manager(&[1, 2, 3]);
+        task_manager.completion_log.insert(day(2), 0);
+        assert_eq!(task_manager.streaks(day(3)), (1, 1));
+    }
+
+    #[test]
+    fn no_completions_means_no_streak() {
+        let (_dir, task_manager) = manager(&[]);
+        assert_eq!(task_manager.streaks(day(1)), (0, 0));
+    }
+
+    #[test]
+    fn checklist_lines_move_out_of_the_description() {
+        let task = &checklist_goal(&["Task"]).tasks[0];
+        assert_eq!(task.description, "Steps:");
+        let items: Vec<_> = task.checklist.iter().map(|i| (&*i.title, i.done)).collect();
+        assert_eq!(items, [("One", false), ("Two", true)]);
+        assert_eq!(task.progress(), 0.5);
+    }
+
+    #[test]
+    fn checking_the_last_item_completes_the_task() {
+        let (_dir, mut task_manager) = manager_of(checklist_goal(&["Task", "Next"]));
+
+        task_manager.toggle_checklist_item(0).unwrap();
+        assert!(task_manager.tasks[0].done);
+        assert_eq!(task_manager.undone_indexes, vec![1]);
+        assert_eq!(task_manager.completion_log.values().sum::<u32>(), 1);
+
+        task_manager.toggle_checklist_item(1).unwrap();
+        assert!(!task_manager.tasks[0].done);
+        assert_eq!(task_manager.undone_indexes, vec![0, 1]);
+        assert!(task_manager.completion_log.is_empty());
+
+        task_manager.toggle_checklist_item(5).unwrap();
+        assert!(!task_manager.tasks[0].checklist[1].done);
+    }
+
+    #[test]
+    fn checklists_do_not_complete_blocked_tasks() {
+        let mut task_list = checklist_goal(&["Task", "Prerequisite"]);
+        task_list.tasks[0].depends_on = vec![2];
+        let (_dir, mut task_manager) = manager_of(task_list);
+
+        task_manager.toggle_checklist_item(0).unwrap();
+
+        assert!(task_manager.tasks[0].checklist.iter().all(|i| i.done));
+        assert!(!task_manager.tasks[0].done);
+    }
+
+    #[test]
+    fn dependency_cycles_are_rejected() {
+        let mut task_list = TaskList::for_test(&["First", "Second", "Third"]);
+        task_list.tasks[1].depends_on = vec![1];
+        task_list.tasks[2].depends_on = vec![2];
+        let (_dir, task_manager) = manager_of(task_list);
+
+        let mut first = task_manager.tasks[0].clone();
+        first.depends_on = vec![1];
+        assert!(task_manager.check_task(&first).is_err());
+        first.depends_on = vec![3];
+        assert!(task_manager.check_task(&first).is_err());
+
+        let mut third = task_manager.tasks[2].clone();
+        third.depends_on = vec![1, 2, 4];
+        assert!(task_manager.check_task(&third).is_ok());
+    }
+
+    #[test]
+    fn blocked_tasks_are_not_done_by_editing() {
+        let mut task_list = TaskList::for_test(&["First", "Second"]);
+        task_list.tasks[1].depends_on = vec![1];
+        let (_dir, mut task_manager) = manager_of(task_list);
+
+        let mut second = task_manager.tasks[1].clone();
+        second.done = true;
+        assert_eq!(
+            task_manager.check_task(&second).unwrap_err(),
+            "Task #2 can't be done while it is blocked by #1"
+        );
+
+        task_manager.tasks[1].done = true;
+        assert!(task_manager.check_task(&second).is_ok());
+    }
+
+    #[test]
+    fn undone_navigation_skips_tasks_not_started_yet() {
+        let today = Local::now().date_naive();
+        let mut task_list = TaskList::for_test(&["Later", "Now", "Later too", "Started"]);
+        task_list.tasks[0].start_after = today.checked_add_days(Days::new(1));
+        task_list.tasks[2].start_after = today.checked_add_days(Days::new(7));
+        task_list.tasks[3].start_after = today.checked_sub_days(Days::new(1));
+        let (_dir, mut task_manager) = manager_of(task_list);
+
+        task_manager.first_undone();
+        assert_eq!(task_manager.current_index, 1);
+        task_manager.next_undone();
+        assert_eq!(task_manager.current_index, 3);
+        task_manager.next_undone();
+        assert_eq!(task_manager.current_index, 3);
+    }
+
+    #[test]
+    fn first_undone_falls_back_to_tasks_not_started_yet() {
+        let mut task_list = TaskList::for_test(&["Done", "Later"]);
+        task_list.tasks[0].done = true;
+        task_list.tasks[1].start_after = Local::now().date_naive().checked_add_days(Days::new(1));
+        let (_dir, mut task_manager) = manager_of(task_list);
+
+        task_manager.first_undone();
+        assert_eq!(task_manager.current_index, 1);
+    }
+
+    #[test]
+    fn undone_tasks_inserted_on_disk_are_found_after_a_reload() {
+        let mut task_list = TaskList::for_test(&["A", "B"]);
+        task_list.tasks[1].done = true;
+        let (dir, mut task_manager) = manager_of(task_list);
+        task_manager.save().unwrap();
+        persistence::testing::edit_tasks(dir.path(), |task_list| {
+            task_list.tasks.insert(0, Task::new(3, "C", ""));
+        });
+
+        let task_list = persistence::read_tasks(dir.path()).unwrap();
+        let mut task_manager = TaskManager::new(task_list, dir.path().to_path_buf()).unwrap();
+        assert_eq!(task_manager.current_task().title, "C");
+        task_manager.mark_done().unwrap();
+
+        assert!(!task_manager.is_done());
+        task_manager.next_undone();
+        assert_eq!(task_manager.current_task().title, "A");
+    }
+
+    #[test]
+    fn refused_saves_keep_the_changes_unsaved() {
+        let (dir, mut task_manager) = manager_of(TaskList::for_test(&["Plan", "Build"]));
+        task_manager.save().unwrap();
+        assert!(!task_manager.unsaved);
+        persistence::testing::edit_tasks(dir.path(), |task_list| {
+            task_list.tasks[1].title = "Build it".to_string();
+        });
+
+        let error = task_manager.mark_done().unwrap_err();
+
+        assert!(error.get_ref().unwrap().is::<ChangedOnDisk>());
+        assert!(task_manager.conflict && task_manager.unsaved);
+        let on_disk = persistence::read_tasks(dir.path()).unwrap();
+        assert!(!on_disk.tasks[0].done);
     }
 }
@@ -0,0 +1,45 @@
+//! Notices when the tasks file of a goal is changed by another program.
+
+use crate::persistence;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+
+/// Watches the directory of a goal for changes to its tasks file.
+pub struct TasksWatcher {
+    /// Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<()>,
+}
+
+impl TasksWatcher {
+    /// Starts watching the tasks file of the goal in `dir`.
+    ///
+    /// The directory is watched rather than the file, since saves replace the
+    /// file by renaming a new one over it.
+    pub fn new(dir: &Path) -> notify::Result<TasksWatcher> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            let touches_tasks = event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == Some(persistence::TASKS_FILE.as_ref()));
+            if touches_tasks && !event.kind.is_access() {
+                let _ = sender.send(());
+            }
+        })?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(TasksWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Returns `true` if the tasks file was touched since the last call.
+    pub fn changed(&self) -> bool {
+        self.events.try_iter().count() > 0
+    }
+}
@@ -0,0 +1,321 @@
+//! Sync through a WebDAV server, e.g. Nextcloud or `rclone serve webdav`.
+
+use crate::config::WebDavConfig;
+use crate::persistence;
+use crate::sync::{Error, RemoteFile, RemoteStore, Result};
+use base64::Engine;
+use std::io;
+use std::time::Duration;
+
+/// How long to wait for the server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keeps the tasks file of each goal in a collection on a WebDAV server.
+pub struct WebDavStore {
+    url: Option<String>,
+    authorization: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl WebDavStore {
+    /// Creates a store for the server in `config`, which is unconfigured without a URL.
+    pub fn new(config: &WebDavConfig) -> WebDavStore {
+        let authorization = config.username.as_ref().map(|username| {
+            let credentials = format!(
+                "{}:{}",
+                username,
+                config.password.as_deref().unwrap_or_default()
+            );
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            )
+        });
+        WebDavStore {
+            url: config
+                .url
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_string()),
+            authorization,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Returns the URL of the collection of `goal`.
+    fn collection(&self, goal: &str) -> Option<String> {
+        self.url
+            .as_ref()
+            .map(|url| format!("{}/{}/", url, encode(goal)))
+    }
+
+    fn file(&self, goal: &str) -> Option<String> {
+        self.collection(goal)
+            .map(|collection| collection + persistence::TASKS_FILE)
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = self.agent.request(method, url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+
+    fn put(&self, url: &str, content: &str, version: Option<&str>) -> Result<()> {
+        let request = match version {
+            Some(version) => self.request("PUT", url).set("If-Match", version),
+            None => self.request("PUT", url).set("If-None-Match", "*"),
+        };
+        request
+            .set("Content-Type", "application/toml")
+            .send_string(content)
+            .map_err(|e| http_error("PUT", url, e))?;
+        Ok(())
+    }
+}
+
+impl RemoteStore for WebDavStore {
+    fn location(&self, goal: &str) -> Option<String> {
+        self.file(goal)
+    }
+
+    fn read(&self, goal: &str) -> Result<Option<RemoteFile>> {
+        let Some(url) = self.file(goal) else {
+            return Ok(None);
+        };
+        let response = match self.request("GET", &url).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(http_error("GET", &url, e)),
+        };
+        let version = response.header("ETag").map(str::to_string);
+        Ok(Some(RemoteFile {
+            content: response.into_string()?,
+            version,
+        }))
+    }
+
+    /// Creates the collection of `goal` first if the server reports it missing.
+    fn write(&self, goal: &str, content: &str, version: Option<&str>) -> Result<()> {
+        let (Some(collection), Some(url)) = (self.collection(goal), self.file(goal)) else {
+            return Ok(());
+        };
+        match self.put(&url, content, version) {
+            Err(Error::Http {
+                status: 404 | 409, ..
+            }) => {
+                self.request("MKCOL", &collection)
+                    .call()
+                    .map_err(|e| http_error("MKCOL", &collection, e))?;
+                self.put(&url, content, version)
+            }
+            Err(Error::Http { status: 412, .. }) => Err(Error::Io(io::Error::other(
+                "The tasks on the server changed during the push; push again to merge them",
+            ))),
+            result => result,
+        }
+    }
+}
+
+/// Percent-encodes `segment` for use as a single path segment of a URL.
+fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn http_error(method: &str, url: &str, error: ureq::Error) -> Error {
+    match error {
+        ureq::Error::Status(status, response) => Error::Http {
+            request: format!("{} {}", method, url),
+            status,
+            output: response
+                .into_string()
+                .map(|body| body.trim().to_string())
+                .unwrap_or_default(),
+        },
+        ureq::Error::Transport(e) => Error::Io(io::Error::other(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// A request received by [`serve`].
+    #[derive(Debug)]
+    struct Request {
+        method: String,
+        path: String,
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Answers one request per entry of `responses`, given as status, extra
+    /// header lines and body, on a local port.
+    ///
+    /// Returns the URL of the server and a handle yielding the requests received.
+    fn serve(
+        responses: Vec<(u16, &'static str, &'static str)>,
+    ) -> (String, JoinHandle<Vec<Request>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dav", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, headers, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+                let mut request = Request {
+                    method: method.to_string(),
+                    path: path.to_string(),
+                    headers: Vec::new(),
+                    body: String::new(),
+                };
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((key, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    request.headers.push((key.to_string(), value.to_string()));
+                }
+                let length = request
+                    .header("Content-Length")
+                    .map_or(0, |length| length.parse().unwrap());
+                let mut content = vec![0; length];
+                reader.read_exact(&mut content).unwrap();
+                request.body = String::from_utf8(content).unwrap();
+                let headers = if headers.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}\r\n", headers)
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+                    status,
+                    body.len(),
+                    headers,
+                    body
+                )
+                .unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn store(url: &str) -> WebDavStore {
+        WebDavStore::new(&WebDavConfig {
+            url: Some(format!("{}/", url)),
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+        })
+    }
+
+    #[test]
+    fn reads_the_tasks_file_with_its_version() {
+        let (url, server) = serve(vec![
+            (200, "ETag: \"v1\"", "tasks = []\n"),
+            (404, "", "Not Found"),
+        ]);
+        let store = store(&url);
+
+        let file = store.read("my goal").unwrap().unwrap();
+        assert_eq!(file.content, "tasks = []\n");
+        assert_eq!(file.version.as_deref(), Some("\"v1\""));
+        assert!(store.read("my goal").unwrap().is_none());
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/dav/my%20goal/tasks.toml");
+        assert_eq!(
+            requests[0].header("Authorization"),
+            Some("Basic dXNlcjpzZWNyZXQ=")
+        );
+    }
+
+    #[test]
+    fn writes_only_over_the_version_read() {
+        let (url, server) = serve(vec![(204, "", ""), (201, "", "")]);
+        let store = store(&url);
+
+        store.write("work", "tasks = []\n", Some("\"v1\"")).unwrap();
+        store.write("work", "tasks = []\n", None).unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].body, "tasks = []\n");
+        assert_eq!(requests[0].header("If-Match"), Some("\"v1\""));
+        assert_eq!(requests[0].header("If-None-Match"), None);
+        assert_eq!(requests[1].header("If-None-Match"), Some("*"));
+        assert_eq!(requests[1].header("If-Match"), None);
+    }
+
+    #[test]
+    fn failed_precondition_asks_to_push_again() {
+        let (url, server) = serve(vec![(412, "", "Precondition Failed")]);
+
+        let error = store(&url).write("work", "", Some("\"v1\"")).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "The tasks on the server changed during the push; push again to merge them"
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn creates_a_missing_collection() {
+        for status in [404, 409] {
+            let (url, server) = serve(vec![(status, "", ""), (201, "", ""), (201, "", "")]);
+
+            store(&url).write("work", "tasks = []\n", None).unwrap();
+
+            let requests = server.join().unwrap();
+            let methods: Vec<&str> = requests.iter().map(|r| r.method.as_str()).collect();
+            assert_eq!(methods, ["PUT", "MKCOL", "PUT"]);
+            assert_eq!(requests[1].path, "/dav/work/");
+            assert_eq!(requests[2].path, "/dav/work/tasks.toml");
+        }
+    }
+
+    #[test]
+    fn reports_other_errors_with_the_request() {
+        let (url, server) = serve(vec![(500, "", "Internal Server Error\n")]);
+
+        let error = store(&url).read("work").err().unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "`GET {}/work/tasks.toml` failed with status 500: Internal Server Error",
+                url
+            )
+        );
+        server.join().unwrap();
+    }
+}